@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE "server_info" ADD COLUMN "region" TEXT;
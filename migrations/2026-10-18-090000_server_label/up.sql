@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE "server_label" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "key" TEXT NOT NULL,
+    "value" TEXT NOT NULL,
+    "server_info_id" UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP,
+    FOREIGN KEY ("server_info_id") REFERENCES "server_info"("id"),
+    UNIQUE ("server_info_id", "key")
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "server_game" DROP COLUMN IF EXISTS "versions";
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE "server_game" ADD COLUMN "versions" TEXT;
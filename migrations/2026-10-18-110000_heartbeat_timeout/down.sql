@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "server_info" DROP COLUMN IF EXISTS "heartbeat_timeout";
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+-- The hashes cannot be reverted. The servers are registered again with their
+-- next update and the old entries are removed by the reaper.
+SELECT 1;
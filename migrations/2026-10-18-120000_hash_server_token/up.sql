@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+UPDATE "server" SET "token" = 'sha256:' || encode(sha256("token"::bytea), 'hex')
+WHERE "token" NOT LIKE '$argon2%' AND "token" NOT LIKE 'sha256:%';
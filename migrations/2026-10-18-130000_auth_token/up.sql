@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE "auth_token" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "name" TEXT,
+    "digest" TEXT NOT NULL UNIQUE,
+    "games" TEXT,
+    "developer" BOOLEAN NOT NULL DEFAULT TRUE,
+    "fallback" BOOLEAN NOT NULL DEFAULT TRUE,
+    "fast_tokens" BOOLEAN NOT NULL DEFAULT TRUE,
+    "disabled" BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "auth_token" DROP COLUMN IF EXISTS "signing_key";
+ALTER TABLE "auth_token" DROP COLUMN IF EXISTS "key_id";
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE "auth_token" ADD COLUMN "key_id" TEXT UNIQUE;
+ALTER TABLE "auth_token" ADD COLUMN "signing_key" TEXT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "fast_token" DROP COLUMN IF EXISTS "expires_at";
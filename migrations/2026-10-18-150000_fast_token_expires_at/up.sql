@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE "fast_token" ADD COLUMN "expires_at" TIMESTAMP;
+UPDATE "fast_token" SET "expires_at" = "created_at" + INTERVAL '20 minutes';
+ALTER TABLE "fast_token" ALTER COLUMN "expires_at" SET NOT NULL;
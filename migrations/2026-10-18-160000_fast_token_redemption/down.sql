@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS "fast_token_redemption";
+ALTER TABLE "fast_token" DROP COLUMN IF EXISTS "uses";
+ALTER TABLE "fast_token" DROP COLUMN IF EXISTS "max_uses";
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+ALTER TABLE "fast_token" ADD COLUMN "max_uses" INTEGER;
+ALTER TABLE "fast_token" ADD COLUMN "uses" INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE "fast_token_redemption" (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "fast_token_id" UUID,
+    "token" TEXT NOT NULL,
+    "server_id" UUID NOT NULL,
+    "client" TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY ("fast_token_id") REFERENCES "fast_token"("id") ON DELETE SET NULL
+);
+CREATE INDEX ON "fast_token_redemption" ("server_id", "token");
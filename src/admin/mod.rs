@@ -0,0 +1,4 @@
+pub mod model;
+mod routes;
+
+pub use routes::init_routes;
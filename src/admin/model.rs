@@ -0,0 +1,97 @@
+use diesel::pg::PgConnection;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::v1::model::GameServer;
+
+/// A registered server as seen by the admins.
+#[derive(Serialize, Deserialize)]
+pub struct AdminServer {
+    #[serde(flatten)]
+    pub server: GameServer,
+    /// the masked identity of the server token
+    pub token: String,
+    #[serde(rename = "token-name")]
+    pub token_name: Option<String>,
+}
+
+impl AdminServer {
+    pub fn new(conn: &PgConnection, server: GameServer, identity: &str) -> Self {
+        AdminServer {
+            server,
+            token: crate::tokens::mask(identity),
+            token_name: crate::tokens::name_of(conn, identity),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MaintenanceRequest {
+    pub maintenance: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RevokeResponse {
+    pub revoked: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReloadResponse {
+    pub servers: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuthTokenRequest {
+    pub name: Option<String>,
+    /// the games the servers of this token may host. `None` allows all games.
+    pub games: Option<Vec<String>>,
+    #[serde(default = "default_true")]
+    pub developer: bool,
+    #[serde(default = "default_true")]
+    pub fallback: bool,
+    #[serde(rename = "fast-tokens", default = "default_true")]
+    pub fast_tokens: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A token in the database. The token itself is only known after it is
+/// created or rotated.
+#[derive(Serialize, Deserialize)]
+pub struct AuthTokenResponse {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub name: Option<String>,
+    pub games: Option<Vec<String>>,
+    pub developer: bool,
+    pub fallback: bool,
+    #[serde(rename = "fast-tokens")]
+    pub fast_tokens: bool,
+    pub disabled: bool,
+    #[serde(rename = "created-at")]
+    pub created_at: String,
+}
+
+impl From<crate::db::model::AuthToken> for AuthTokenResponse {
+    fn from(value: crate::db::model::AuthToken) -> Self {
+        AuthTokenResponse {
+            id: value.id.to_simple()
+                .encode_lower(&mut Uuid::encode_buffer())
+                .to_string(),
+            token: None,
+            name: value.name,
+            games: value.games.map(|x| x.split(',')
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect()
+            ),
+            developer: value.developer,
+            fallback: value.fallback,
+            fast_tokens: value.fast_tokens,
+            disabled: value.disabled,
+            created_at: value.created_at.to_string(),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::convert::TryInto;
+
+use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use uuid::Uuid;
+use super::model::*;
+use crate::api_error::ApiError;
+use crate::tokens::Token;
+use crate::v1::model::{GameServer, UpdateResponse};
+use crate::v1::routes::{error_response, get_header};
+
+/// Checks the admin token in the request header.
+async fn authorized_admin(req: &web::HttpRequest) -> Result<Token, HttpResponse> {
+    let token = match get_header(req, "token") {
+        Some(x) => x,
+        None => return Err(HttpResponse::Forbidden().finish()),
+    };
+    crate::tokens::find_admin(token)
+        .await
+        .ok_or_else(|| HttpResponse::Forbidden().finish())
+}
+
+fn admin_name(admin: &Token) -> &str {
+    admin.scope.name.as_deref().unwrap_or("unnamed")
+}
+
+fn parse_id(id: &str) -> Result<Uuid, HttpResponse> {
+    Uuid::parse_str(id)
+        .map_err(|_| HttpResponse::NotFound().finish())
+}
+
+#[get("/admin/servers")]
+async fn servers(req: web::HttpRequest) -> impl Responder {
+    if let Err(response) = authorized_admin(&req).await {
+        return response;
+    }
+    let result = crate::db::snapshot(|conn| {
+        crate::db::model::Server::find_by_filter(conn, true, true, true)?
+            .into_iter()
+            .map(|(server, server_info)| {
+                let identity = server.token.clone();
+                let server: GameServer = (conn, server, server_info).try_into()?;
+                Ok(AdminServer::new(conn, server, &identity))
+            })
+            .collect::<Result<Vec<_>, ApiError>>()
+    });
+    match result {
+        Ok(x) => HttpResponse::Ok().json(x),
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/admin/servers/{server_id}")]
+async fn server_delete(req: web::HttpRequest, server_id: web::Path<String>) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let id = match parse_id(&server_id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let exists = crate::db::connection()
+        .and_then(|conn| crate::db::model::Server::find_by_id(&conn, id));
+    if let Err(err) = exists {
+        return error_response(err);
+    }
+    match GameServer::delete(id) {
+        Ok(()) => {
+            info!("admin {} removed server {}", admin_name(&admin), id);
+            HttpResponse::Ok().json(UpdateResponse::from(id))
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+#[put("/admin/servers/{server_id}/maintenance")]
+async fn server_maintenance(
+    req: web::HttpRequest,
+    server_id: web::Path<String>,
+    request: web::Json<MaintenanceRequest>
+) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let id = match parse_id(&server_id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    match GameServer::set_maintenance(id, request.maintenance) {
+        Ok(()) => {
+            info!(
+                "admin {} set maintenance of server {} to {}",
+                admin_name(&admin), id, request.maintenance
+            );
+            HttpResponse::Ok().json(UpdateResponse::from(id))
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/admin/servers/{server_id}/tokens")]
+async fn server_tokens_delete(req: web::HttpRequest, server_id: web::Path<String>) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let id = match parse_id(&server_id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::FastToken::delete_by_server(&conn, id));
+    match result {
+        Ok(revoked) => {
+            info!(
+                "admin {} revoked {} fast tokens of server {}",
+                admin_name(&admin), revoked, id
+            );
+            HttpResponse::Ok().json(RevokeResponse { revoked })
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/admin/tokens/{token}")]
+async fn token_delete(req: web::HttpRequest, token: web::Path<String>) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let token = crate::v1::fast_token::normalize(&token);
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::FastToken::delete_by_token(&conn, &token));
+    match result {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(revoked) => {
+            info!("admin {} revoked fast token {}", admin_name(&admin), token);
+            HttpResponse::Ok().json(RevokeResponse { revoked })
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+#[post("/admin/reload")]
+async fn reload(req: web::HttpRequest) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    info!("admin {} reloads the tokens", admin_name(&admin));
+    match crate::tokens::reload() {
+        Ok(count) => HttpResponse::Ok().json(ReloadResponse { servers: count }),
+        Err(e) => error_response(ApiError::new(500, format!("cannot reload token file: {}", e))),
+    }
+}
+
+#[get("/admin/auth-tokens")]
+async fn auth_tokens(req: web::HttpRequest) -> impl Responder {
+    if let Err(response) = authorized_admin(&req).await {
+        return response;
+    }
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::AuthToken::find_all(&conn));
+    match result {
+        Ok(x) => HttpResponse::Ok().json(x.into_iter()
+            .map(AuthTokenResponse::from)
+            .collect::<Vec<_>>()
+        ),
+        Err(err) => error_response(err),
+    }
+}
+
+#[post("/admin/auth-tokens")]
+async fn auth_token_post(
+    req: web::HttpRequest,
+    request: web::Json<AuthTokenRequest>
+) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request = request.into_inner();
+    let token = crate::tokens::new_token();
+    let (key_id, signing_key) = crate::tokens::signing_columns(&token);
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::AuthToken::create(
+            &conn,
+            crate::db::model::AuthToken {
+                id: Uuid::new_v4(),
+                name: request.name,
+                digest: crate::tokens::digest_hex(&token),
+                key_id,
+                signing_key,
+                games: request.games.map(|x| x.join(",")),
+                developer: request.developer,
+                fallback: request.fallback,
+                fast_tokens: request.fast_tokens,
+                disabled: false,
+                created_at: chrono::Utc::now().naive_utc(),
+                updated_at: None,
+            }
+        ));
+    match result {
+        Ok(x) => {
+            info!("admin {} created token {}", admin_name(&admin), x.id);
+            let mut response = AuthTokenResponse::from(x);
+            response.token = Some(token);
+            HttpResponse::Ok().json(response)
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+#[post("/admin/auth-tokens/{token_id}/disable")]
+async fn auth_token_disable(req: web::HttpRequest, token_id: web::Path<String>) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let id = match parse_id(&token_id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let result = crate::db::transaction(|conn| {
+        let mut token = crate::db::model::AuthToken::find_by_id(conn, id)?;
+        token.disabled = true;
+        token.updated_at = Some(chrono::Utc::now().naive_utc());
+        crate::db::model::AuthToken::update(conn, token)
+    });
+    match result {
+        Ok(x) => {
+            info!("admin {} disabled token {}", admin_name(&admin), id);
+            let identity = crate::tokens::auth_identity(id);
+            if let Err(err) = crate::tokens::revoke(&identity, x.name.as_deref()) {
+                return error_response(err);
+            }
+            HttpResponse::Ok().json(AuthTokenResponse::from(x))
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+/// Replaces the token with a new one. The server of the token stays registered
+/// and the game server only has to use the new token for its next request.
+#[post("/admin/auth-tokens/{token_id}/rotate")]
+async fn auth_token_rotate(req: web::HttpRequest, token_id: web::Path<String>) -> impl Responder {
+    let admin = match authorized_admin(&req).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let id = match parse_id(&token_id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let token = crate::tokens::new_token();
+    let (key_id, signing_key) = crate::tokens::signing_columns(&token);
+    let result = crate::db::transaction(|conn| {
+        let mut entry = crate::db::model::AuthToken::find_by_id(conn, id)?;
+        entry.digest = crate::tokens::digest_hex(&token);
+        entry.key_id = key_id;
+        entry.signing_key = signing_key;
+        entry.updated_at = Some(chrono::Utc::now().naive_utc());
+        crate::db::model::AuthToken::update(conn, entry)
+    });
+    match result {
+        Ok(x) => {
+            info!("admin {} rotated token {}", admin_name(&admin), id);
+            let mut response = AuthTokenResponse::from(x);
+            response.token = Some(token);
+            HttpResponse::Ok().json(response)
+        },
+        Err(err) => error_response(err),
+    }
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(servers);
+    cfg.service(server_delete);
+    cfg.service(server_maintenance);
+    cfg.service(server_tokens_delete);
+    cfg.service(token_delete);
+    cfg.service(reload);
+    cfg.service(auth_tokens);
+    cfg.service(auth_token_post);
+    cfg.service(auth_token_disable);
+    cfg.service(auth_token_rotate);
+}
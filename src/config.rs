@@ -0,0 +1,269 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+/// The settings of a single game. They are read from the JSON file in
+/// `GAME_CONFIG` which maps each game name to its settings.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GameConfig {
+    pub strategy: Option<crate::v1::selection::Strategy>,
+    /// overrides the global fast token settings for this game
+    #[serde(default)]
+    pub fast_token: FastTokenOverrides,
+}
+
+/// The fast token settings of a game. Settings that are not set are taken from
+/// the global config.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct FastTokenOverrides {
+    pub length: Option<usize>,
+    pub alphabet: Option<String>,
+    pub ttl: Option<u32>,
+    pub min_ttl: Option<u32>,
+    pub max_ttl: Option<u32>,
+}
+
+/// The settings for the creation of fast tokens.
+pub struct FastTokenConfig {
+    /// the number of characters of a token
+    pub length: usize,
+    /// the characters a token consists of
+    pub alphabet: Vec<char>,
+    /// if the alphabet is the Crockford alphabet and the lookup of the tokens
+    /// accepts the confusable characters
+    pub crockford: bool,
+    /// the default number of seconds a token is valid
+    pub ttl: u32,
+    /// the lowest ttl a game server can request
+    pub min_ttl: u32,
+    /// the highest ttl a game server can request
+    pub max_ttl: u32,
+}
+
+impl FastTokenConfig {
+    fn from_env() -> Self {
+        let alphabet: String = env_or(
+            "FAST_TOKEN_ALPHABET",
+            "ABCDEFGHIJKLMOPQRSTUVWXYZ0123456789".to_string()
+        );
+        let (alphabet, crockford) = parse_alphabet(&alphabet);
+        FastTokenConfig {
+            length: env_or("FAST_TOKEN_LENGTH", 4),
+            alphabet,
+            crockford,
+            ttl: env_or("FAST_TOKEN_TTL", 1200),
+            min_ttl: env_or("FAST_TOKEN_MIN_TTL", 60),
+            max_ttl: env_or("FAST_TOKEN_MAX_TTL", 3600),
+        }
+    }
+
+    fn merge(&self, overrides: &FastTokenOverrides) -> Self {
+        let (alphabet, crockford) = match &overrides.alphabet {
+            Some(x) => parse_alphabet(x),
+            None => (self.alphabet.clone(), self.crockford),
+        };
+        FastTokenConfig {
+            length: overrides.length.unwrap_or(self.length),
+            alphabet,
+            crockford,
+            ttl: overrides.ttl.unwrap_or(self.ttl),
+            min_ttl: overrides.min_ttl.unwrap_or(self.min_ttl),
+            max_ttl: overrides.max_ttl.unwrap_or(self.max_ttl),
+        }
+    }
+
+    /// Panics if the settings cannot be used to create tokens. The tokens are
+    /// case insensitive, so the alphabet can only contain uppercase letters and
+    /// digits.
+    fn validate(self, name: &str) -> Self {
+        if self.length == 0 {
+            panic!("fast token length of {} must be positive", name);
+        }
+        if self.alphabet.iter().any(|x| !x.is_ascii_uppercase() && !x.is_ascii_digit()) {
+            panic!("fast token alphabet of {} may only contain A-Z and 0-9", name);
+        }
+        let mut unique = self.alphabet.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != self.alphabet.len() || unique.len() < 2 {
+            panic!("fast token alphabet of {} needs at least two distinct characters", name);
+        }
+        if self.min_ttl > self.ttl || self.ttl > self.max_ttl {
+            panic!("fast token ttl of {} must be between the min and max ttl", name);
+        }
+        self
+    }
+}
+
+/// Parses the alphabet of the fast tokens. `crockford` selects the Crockford
+/// base 32 alphabet.
+fn parse_alphabet(value: &str) -> (Vec<char>, bool) {
+    if value.eq_ignore_ascii_case("crockford") {
+        (crate::v1::fast_token::CROCKFORD_ALPHABET.chars().collect(), true)
+    } else {
+        (value.chars().collect(), false)
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(x) => x.parse()
+            .unwrap_or_else(|_| panic!("invalid value for {}", name)),
+        Err(_) => default,
+    }
+}
+
+/// Like `env_or`, but rejects `0`. Used for the periods of timers that cannot
+/// be zero.
+fn env_positive(name: &str, default: u32) -> u32 {
+    let value = env_or(name, default);
+    if value == 0 {
+        panic!("{} must be greater than 0", name);
+    }
+    value
+}
+
+lazy_static! {
+    static ref HEARTBEAT_TIMEOUT: u32 = env_or("HEARTBEAT_TIMEOUT", 60);
+    static ref SERVER_RETENTION: u32 = env_or("SERVER_RETENTION", 3600);
+    static ref REAPER_INTERVAL: u32 = env_positive("REAPER_INTERVAL", 60);
+    static ref REDEMPTION_RETENTION: u32 = env_or("REDEMPTION_RETENTION", 604800);
+    static ref TOKEN_WATCH_INTERVAL: u32 = env_positive("TOKEN_WATCH_INTERVAL", 5);
+    static ref SIGNATURE_MAX_AGE: u32 = env_or("SIGNATURE_MAX_AGE", 300);
+    static ref REQUIRE_SIGNATURE: bool = env_or("REQUIRE_SIGNATURE", false);
+    static ref SIGNING_KEY_SECRET: Option<String> = env::var("SIGNING_KEY_SECRET").ok()
+        .filter(|x| !x.is_empty());
+    static ref FAST_TOKEN: FastTokenConfig = FastTokenConfig::from_env().validate("FAST_TOKEN");
+    static ref RATE_LIMIT_LOOKUP: u32 = env_or("RATE_LIMIT_LOOKUP", 30);
+    static ref RATE_LIMIT_MATCHMAKING: u32 = env_or("RATE_LIMIT_MATCHMAKING", 120);
+    static ref RATE_LIMIT_SERVER: u32 = env_or("RATE_LIMIT_SERVER", 600);
+    static ref RATE_LIMIT_TRUST_PROXY: bool = env_or("RATE_LIMIT_TRUST_PROXY", false);
+
+    static ref GAMES: HashMap<String, GameConfig> = {
+        let path = match env::var("GAME_CONFIG") {
+            Ok(x) => x,
+            Err(_) => return HashMap::new(),
+        };
+        let file = File::open(path).expect("cannot open game config");
+        let games: HashMap<String, GameConfig> = serde_json::from_reader(io::BufReader::new(file))
+            .expect("invalid game config");
+        info!("{} games are configured", games.len());
+        games
+    };
+
+    static ref GAME_FAST_TOKENS: HashMap<String, FastTokenConfig> = GAMES.iter()
+        .map(|(game, config)| (game.clone(), FAST_TOKEN.merge(&config.fast_token).validate(game)))
+        .collect();
+
+    static ref CROCKFORD_LOOKUP: bool = FAST_TOKEN.crockford
+        || GAME_FAST_TOKENS.values().any(|x| x.crockford);
+}
+
+pub fn init() {
+    info!("Initializing Config");
+    lazy_static::initialize(&HEARTBEAT_TIMEOUT);
+    lazy_static::initialize(&SERVER_RETENTION);
+    lazy_static::initialize(&REAPER_INTERVAL);
+    lazy_static::initialize(&REDEMPTION_RETENTION);
+    lazy_static::initialize(&TOKEN_WATCH_INTERVAL);
+    lazy_static::initialize(&SIGNATURE_MAX_AGE);
+    lazy_static::initialize(&REQUIRE_SIGNATURE);
+    lazy_static::initialize(&SIGNING_KEY_SECRET);
+    if SIGNING_KEY_SECRET.is_none() {
+        info!("SIGNING_KEY_SECRET is not set, tokens of the admin API cannot sign requests");
+    }
+    lazy_static::initialize(&RATE_LIMIT_LOOKUP);
+    lazy_static::initialize(&RATE_LIMIT_MATCHMAKING);
+    lazy_static::initialize(&RATE_LIMIT_SERVER);
+    lazy_static::initialize(&RATE_LIMIT_TRUST_PROXY);
+    lazy_static::initialize(&GAMES);
+    lazy_static::initialize(&FAST_TOKEN);
+    lazy_static::initialize(&GAME_FAST_TOKENS);
+    lazy_static::initialize(&CROCKFORD_LOOKUP);
+}
+
+/// The default number of seconds after the last update in which a server is
+/// considered online.
+pub fn heartbeat_timeout() -> u32 {
+    *HEARTBEAT_TIMEOUT
+}
+
+/// The number of seconds after the last update after which a server is removed.
+pub fn server_retention() -> u32 {
+    *SERVER_RETENTION
+}
+
+/// The number of seconds between two runs of the reaper.
+pub fn reaper_interval() -> u32 {
+    *REAPER_INTERVAL
+}
+
+/// The number of seconds the redemptions of fast tokens are kept.
+pub fn redemption_retention() -> u32 {
+    *REDEMPTION_RETENTION
+}
+
+/// The number of seconds between two checks if the token file was changed.
+pub fn token_watch_interval() -> u32 {
+    *TOKEN_WATCH_INTERVAL
+}
+
+/// The number of seconds the timestamp of a signed request may differ from the
+/// current time.
+pub fn signature_max_age() -> u32 {
+    *SIGNATURE_MAX_AGE
+}
+
+/// If game servers must sign their requests instead of sending the token.
+pub fn require_signature() -> bool {
+    *REQUIRE_SIGNATURE
+}
+
+/// The secret that encrypts the signing keys in the database. Without it the
+/// tokens in the database cannot sign requests.
+pub fn signing_key_secret() -> Option<&'static str> {
+    SIGNING_KEY_SECRET.as_deref()
+}
+
+/// The number of fast token lookups per minute and client IP.
+pub fn rate_limit_lookup() -> u32 {
+    *RATE_LIMIT_LOOKUP
+}
+
+/// The number of requests to `/v1/new`, `/v1/list` and `/v1/info` per minute
+/// and client IP.
+pub fn rate_limit_matchmaking() -> u32 {
+    *RATE_LIMIT_MATCHMAKING
+}
+
+/// The number of requests of game servers per minute and client IP.
+pub fn rate_limit_server() -> u32 {
+    *RATE_LIMIT_SERVER
+}
+
+/// If the client IP is taken from the last entry of the `X-Forwarded-For`
+/// header.
+pub fn rate_limit_trust_proxy() -> bool {
+    *RATE_LIMIT_TRUST_PROXY
+}
+
+/// The fast token settings for the game.
+pub fn fast_token(game: &str) -> &'static FastTokenConfig {
+    GAME_FAST_TOKENS.get(game).unwrap_or(&FAST_TOKEN)
+}
+
+/// If any game uses the Crockford alphabet and the lookup has to accept the
+/// confusable characters.
+pub fn crockford_lookup() -> bool {
+    *CROCKFORD_LOOKUP
+}
+
+pub fn games() -> impl Iterator<Item = (&'static String, &'static GameConfig)> {
+    GAMES.iter()
+}
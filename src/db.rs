@@ -1,6 +1,7 @@
 use crate::api_error::ApiError;
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
+use diesel::Connection;
 use lazy_static::lazy_static;
 use r2d2;
 use std::env;
@@ -31,3 +32,26 @@ pub fn connection() -> Result<DbConnection, ApiError> {
     POOL.get()
         .map_err(|e| ApiError::new(500, format!("Failed getting db connection: {}", e)))
 }
+
+/// Runs all statements in a single transaction. If any of them fails, all
+/// changes are rolled back.
+pub fn transaction<T, F>(f: F) -> Result<T, ApiError>
+where
+    F: FnOnce(&PgConnection) -> Result<T, ApiError>,
+{
+    let conn = connection()?;
+    conn.transaction(|| f(&conn))
+}
+
+/// Runs all queries on the same snapshot of the database. They never see
+/// changes of transactions that are committed in between.
+pub fn snapshot<T, F>(f: F) -> Result<T, ApiError>
+where
+    F: FnOnce(&PgConnection) -> Result<T, ApiError>,
+{
+    let conn = connection()?;
+    conn.build_transaction()
+        .read_only()
+        .repeatable_read()
+        .run(|| f(&conn))
+}
@@ -1,9 +1,12 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use crate::api_error::ApiError;
-use crate::schema::{server, server_game, server_info, fast_token};
+use crate::schema::{
+    auth_token, server, server_game, server_info, server_label, fast_token, fast_token_redemption,
+};
 
 #[derive(Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
 #[table_name = "server"]
@@ -16,22 +19,28 @@ pub struct Server {
 }
 
 impl Server {
-    pub fn find_all() -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn find_all(conn: &PgConnection) -> Result<Vec<Self>, ApiError> {
+        let servers = server::table
+            .load::<Server>(conn)?;
+
+        Ok(servers)
+    }
 
+    /// Loads all servers with their info in a single query.
+    pub fn find_all_with_info(conn: &PgConnection) -> Result<Vec<(Self, ServerInfo)>, ApiError> {
         let servers = server::table
-            .load::<Server>(&conn)?;
+            .inner_join(server_info::table)
+            .load::<(Self, ServerInfo)>(conn)?;
 
         Ok(servers)
     }
 
     pub fn find_by_filter(
+        conn: &PgConnection,
         include_dev: bool, 
         include_fallback: bool, 
         exclude_full: bool
     ) -> Result<Vec<(Self, ServerInfo)>, ApiError> {
-        let conn = crate::db::connection()?;
-
         let mut result = server::table
             .inner_join(server_info::table)
             .into_boxed();
@@ -49,59 +58,74 @@ impl Server {
                 .filter(server_info::full.eq(false));
         }
 
-        let result = result.load::<(Self, ServerInfo)>(&conn)?;
+        let result = result.load::<(Self, ServerInfo)>(conn)?;
 
         Ok(result)
     }
 
-    pub fn find_by_id(id: Uuid) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn find_last_seen_before(
+        conn: &PgConnection,
+        limit: NaiveDateTime
+    ) -> Result<Vec<(Self, ServerInfo)>, ApiError> {
+        let servers = server::table
+            .inner_join(server_info::table)
+            .filter(server::last_seen.lt(limit))
+            .load::<(Self, ServerInfo)>(conn)?;
 
+        Ok(servers)
+    }
+
+    pub fn find_by_id(conn: &PgConnection, id: Uuid) -> Result<Self, ApiError> {
         let server = server::table
             .filter(server::id.eq(id))
-            .first(&conn)?;
+            .first(conn)?;
         
         Ok(server)
     }
 
-    pub fn find_by_token(token: &str) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_by_token(conn: &PgConnection, token: &str) -> Result<Self, ApiError> {
         let server = server::table
             .filter(server::token.eq(token))
-            .first(&conn)?;
+            .first(conn)?;
         
         Ok(server)
     }
 
-    pub fn create(server: Server) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn create(conn: &PgConnection, server: Server) -> Result<Self, ApiError> {
         let server = diesel::insert_into(server::table)
             .values(server)
-            .get_result(&conn)?;
+            .get_result(conn)?;
         
         Ok(server)
     }
 
-    pub fn update(server: Server) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn update(conn: &PgConnection, server: Server) -> Result<Self, ApiError> {
         let server = diesel::update(server::table)
             .filter(server::id.eq(server.id))
             .set(server)
-            .get_result(&conn)?;
+            .get_result(conn)?;
         
         Ok(server)
     }
 
-    pub fn delete(id: Uuid) -> Result<usize, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn touch(
+        conn: &PgConnection,
+        id: Uuid,
+        last_seen: NaiveDateTime
+    ) -> Result<usize, ApiError> {
+        let res = diesel::update(server::table)
+            .filter(server::id.eq(id))
+            .set(server::last_seen.eq(last_seen))
+            .execute(conn)?;
+
+        Ok(res)
+    }
 
+    pub fn delete(conn: &PgConnection, id: Uuid) -> Result<usize, ApiError> {
         let res = diesel::delete(
             server::table
                 .filter(server::id.eq(id))
-        ).execute(&conn)?;
+        ).execute(conn)?;
 
         Ok(res)
     }
@@ -109,6 +133,7 @@ impl Server {
 
 #[derive(Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
 #[table_name = "server_info"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct ServerInfo {
     pub id: Uuid,
     pub name: String,
@@ -121,35 +146,40 @@ pub struct ServerInfo {
     pub server_id: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub region: Option<String>,
+    pub heartbeat_timeout: Option<i32>,
 }
 
 impl ServerInfo {
-    pub fn find_all() -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_all(conn: &PgConnection) -> Result<Vec<Self>, ApiError> {
         let infos = server_info::table
-            .load::<ServerInfo>(&conn)?;
+            .load::<ServerInfo>(conn)?;
         
         Ok(infos)
     }
 
-    pub fn find_by_server(server_id: Uuid) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_by_server(conn: &PgConnection, server_id: Uuid) -> Result<Self, ApiError> {
         let info = server_info::table
             .filter(server_info::server_id.eq(server_id))
-            .first(&conn)?;
+            .first(conn)?;
 
         Ok(info)
     }
 
+    pub fn find_all_by_server(conn: &PgConnection, server_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let infos = server_info::table
+            .filter(server_info::server_id.eq(server_id))
+            .load::<ServerInfo>(conn)?;
+
+        Ok(infos)
+    }
+
     pub fn find_by_filter(
+        conn: &PgConnection,
         incl_developer: bool, 
         incl_fallback: bool, 
         excl_full: bool
     ) -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
-
         let mut infos = server_info::table
             .filter(server_info::developer.eq(true))
             .into_boxed();
@@ -163,39 +193,33 @@ impl ServerInfo {
             infos = infos.filter(server_info::full.eq(false));
         }
 
-        let infos = infos.load::<ServerInfo>(&conn)?;
+        let infos = infos.load::<ServerInfo>(conn)?;
 
         Ok(infos)
     }
 
-    pub fn create(value: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn create(conn: &PgConnection, value: Self) -> Result<Self, ApiError> {
         let res = diesel::insert_into(server_info::table)
             .values(value)
-            .get_result(&conn)?;
+            .get_result(conn)?;
         
         Ok(res)
     }
 
-    pub fn update(value: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn update(conn: &PgConnection, value: Self) -> Result<Self, ApiError> {
         let res = diesel::update(server_info::table)
             .filter(server_info::id.eq(value.id))
             .set(value)
-            .get_result(&conn)?;
+            .get_result(conn)?;
         
         Ok(res)
     }
 
-    pub fn delete(id: Uuid) -> Result<usize, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn delete(conn: &PgConnection, id: Uuid) -> Result<usize, ApiError> {
         let res = diesel::delete(
             server_info::table
                 .filter(server_info::id.eq(id))
-        ).execute(&conn)?;
+        ).execute(conn)?;
 
         Ok(res)
     }
@@ -203,6 +227,7 @@ impl ServerInfo {
 
 #[derive(Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
 #[table_name = "server_game"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct ServerGame {
     pub id: Uuid,
     pub name: String,
@@ -213,83 +238,154 @@ pub struct ServerGame {
     pub game_info_id: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub versions: Option<String>,
 }
 
 impl ServerGame {
-    pub fn find_all() -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_all(conn: &PgConnection) -> Result<Vec<Self>, ApiError> {
         let servers = server_game::table
-            .load::<ServerGame>(&conn)?;
+            .load::<ServerGame>(conn)?;
 
         Ok(servers)
     }
 
-    pub fn find_by_id(id: Uuid) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_by_id(conn: &PgConnection, id: Uuid) -> Result<Self, ApiError> {
         let game = server_game::table
             .filter(server_game::id.eq(id))
-            .first(&conn)?;
+            .first(conn)?;
         
         Ok(game)
     }
 
-    pub fn find_by_info(server_info_id: Uuid) -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_by_info(conn: &PgConnection, server_info_id: Uuid) -> Result<Vec<Self>, ApiError> {
         let games = server_game::table
             .filter(server_game::game_info_id.eq(server_info_id))
-            .load::<ServerGame>(&conn)?;
+            .load::<ServerGame>(conn)?;
         
         Ok(games)
     }
 
-    pub fn create(game: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn find_by_info_and_name(
+        conn: &PgConnection,
+        server_info_id: Uuid,
+        name: &str
+    ) -> Result<Self, ApiError> {
+        let game = server_game::table
+            .filter(server_game::game_info_id.eq(server_info_id))
+            .filter(server_game::name.eq(name))
+            .first(conn)?;
+
+        Ok(game)
+    }
 
+    pub fn create(conn: &PgConnection, game: Self) -> Result<Self, ApiError> {
         let server = diesel::insert_into(server_game::table)
             .values(game)
-            .get_result(&conn)?;
+            .get_result(conn)?;
 
         Ok(server)
     }
 
-    pub fn update(game: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn update(conn: &PgConnection, game: Self) -> Result<Self, ApiError> {
         let game = diesel::update(server_game::table)
             .filter(server_game::id.eq(game.id))
             .set(game)
-            .get_result(&conn)?;
+            .get_result(conn)?;
 
         Ok(game)
     }
 
-    pub fn delete(id: Uuid) -> Result<usize, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn delete(conn: &PgConnection, id: Uuid) -> Result<usize, ApiError> {
         let res = diesel::delete(
             server_game::table
                 .filter(server_game::id.eq(id))
-        ).execute(&conn)?;
+        ).execute(conn)?;
 
         Ok(res)
     }
 
-    pub fn delete_by_info(game_info_id: Uuid) -> Result<usize, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn delete_by_info_and_name(
+        conn: &PgConnection,
+        game_info_id: Uuid,
+        name: &str
+    ) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            server_game::table
+                .filter(server_game::game_info_id.eq(game_info_id))
+                .filter(server_game::name.eq(name))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+
+    pub fn update_counters(
+        conn: &PgConnection,
+        game_info_id: Uuid,
+        name: &str,
+        rooms: i32,
+        clients: i32
+    ) -> Result<usize, ApiError> {
+        let res = diesel::update(server_game::table)
+            .filter(server_game::game_info_id.eq(game_info_id))
+            .filter(server_game::name.eq(name))
+            .set((
+                server_game::rooms.eq(rooms),
+                server_game::clients.eq(clients),
+            ))
+            .execute(conn)?;
 
+        Ok(res)
+    }
+
+    pub fn delete_by_info(conn: &PgConnection, game_info_id: Uuid) -> Result<usize, ApiError> {
         let res = diesel::delete(
             server_game::table
                 .filter(server_game::game_info_id.eq(game_info_id))
-        ).execute(&conn)?;
+        ).execute(conn)?;
 
         Ok(res)
     }
 }
 
 #[derive(Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
+#[table_name = "server_label"]
+pub struct ServerLabel {
+    pub id: Uuid,
+    pub key: String,
+    pub value: String,
+    pub server_info_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl ServerLabel {
+    pub fn find_by_info(conn: &PgConnection, server_info_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let labels = server_label::table
+            .filter(server_label::server_info_id.eq(server_info_id))
+            .load::<ServerLabel>(conn)?;
+
+        Ok(labels)
+    }
+
+    pub fn create(conn: &PgConnection, label: Self) -> Result<Self, ApiError> {
+        let label = diesel::insert_into(server_label::table)
+            .values(label)
+            .get_result(conn)?;
+
+        Ok(label)
+    }
+
+    pub fn delete_by_info(conn: &PgConnection, server_info_id: Uuid) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            server_label::table
+                .filter(server_label::server_info_id.eq(server_info_id))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
 #[table_name = "fast_token"]
 pub struct FastToken {
     pub id: Uuid,
@@ -299,78 +395,313 @@ pub struct FastToken {
     pub lobby: String,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+    /// the number of times the token can be redeemed. `None` for no limit.
+    pub max_uses: Option<i32>,
+    /// the number of times the token was redeemed
+    pub uses: i32,
 }
 
 impl FastToken {
-    pub fn find_all() -> Result<Vec<Self>, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_all(conn: &PgConnection) -> Result<Vec<Self>, ApiError> {
         let result = fast_token::table
-            .load::<FastToken>(&conn)?;
+            .load::<FastToken>(conn)?;
         
         Ok(result)
     }
 
-    pub fn find_by_id(id: Uuid) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn find_by_id(conn: &PgConnection, id: Uuid) -> Result<Self, ApiError> {
         let result = fast_token::table
             .filter(fast_token::id.eq(id))
-            .first(&conn)?;
+            .first(conn)?;
 
         Ok(result)
     }
     
-    pub fn find_by_token(token: &String) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
+    pub fn find_by_token(conn: &PgConnection, token: &String) -> Result<Self, ApiError> {
         
         let result = fast_token::table
         .filter(fast_token::token.eq(token))
-        .first(&conn)?;
+        .first(conn)?;
         
         Ok(result)
     }
     
-    pub fn find_by_token_checked(token: &String, limit: NaiveDateTime) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    /// Finds the tokens of the server that are not expired or used up.
+    pub fn find_active_by_server(
+        conn: &PgConnection,
+        server_id: Uuid,
+        now: NaiveDateTime
+    ) -> Result<Vec<Self>, ApiError> {
         let result = fast_token::table
-            .filter(fast_token::token.eq(token))
-            .filter(fast_token::created_at.gt(limit))
-            .first(&conn)?;
+            .filter(fast_token::server_id.eq(server_id))
+            .filter(fast_token::expires_at.gt(now))
+            .filter(fast_token::max_uses.is_null()
+                .or(fast_token::max_uses.gt(fast_token::uses.nullable()))
+            )
+            .order(fast_token::created_at)
+            .load::<FastToken>(conn)?;
 
         Ok(result)
     }
-    
-    pub fn create(entry: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
 
+    /// Counts a lookup of the token. Returns `None` if the token is unknown,
+    /// expired or reached its `max_uses`.
+    pub fn redeem(
+        conn: &PgConnection,
+        token: &str,
+        now: NaiveDateTime
+    ) -> Result<Option<Self>, ApiError> {
+        let result = diesel::update(
+            fast_token::table
+                .filter(fast_token::token.eq(token))
+                .filter(fast_token::expires_at.gt(now))
+                .filter(fast_token::max_uses.is_null()
+                    .or(fast_token::max_uses.gt(fast_token::uses.nullable()))
+                )
+        )
+            .set(fast_token::uses.eq(fast_token::uses + 1))
+            .get_result(conn)
+            .optional()?;
+
+        Ok(result)
+    }
+
+    /// Inserts the token if its code is free. An expired token with the same
+    /// code is replaced. Returns `None` if the code is in use.
+    pub fn try_create(
+        conn: &PgConnection,
+        entry: Self,
+        now: NaiveDateTime
+    ) -> Result<Option<Self>, ApiError> {
+        diesel::delete(
+            fast_token::table
+                .filter(fast_token::token.eq(&entry.token))
+                .filter(fast_token::expires_at.le(now))
+        ).execute(conn)?;
         let result = diesel::insert_into(fast_token::table)
             .values(entry)
-            .get_result(&conn)?;
-        
+            .on_conflict(fast_token::token)
+            .do_nothing()
+            .get_result(conn)
+            .optional()?;
+
         Ok(result)
     }
 
-    pub fn update(entry: Self) -> Result<Self, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn update(conn: &PgConnection, entry: Self) -> Result<Self, ApiError> {
         let result = diesel::update(fast_token::table)
             .filter(fast_token::id.eq(entry.id))
             .set(entry)
-            .get_result(&conn)?;
+            .get_result(conn)?;
 
         Ok(result)
     }
 
-    pub fn delete(id: Uuid) -> Result<usize, ApiError> {
-        let conn = crate::db::connection()?;
-
+    pub fn delete(conn: &PgConnection, id: Uuid) -> Result<usize, ApiError> {
         let res = diesel::delete(
             fast_token::table
                 .filter(fast_token::id.eq(id))
-        ).execute(&conn)?;
+        ).execute(conn)?;
 
         Ok(res)
     }
+
+    pub fn delete_by_server(conn: &PgConnection, server_id: Uuid) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            fast_token::table
+                .filter(fast_token::server_id.eq(server_id))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+
+    /// Deletes the tokens of all these servers. Returns the number of deleted
+    /// tokens.
+    pub fn delete_by_servers(conn: &PgConnection, server_ids: &[Uuid]) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            fast_token::table
+                .filter(fast_token::server_id.eq_any(server_ids))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+
+    /// Deletes the token if it belongs to the server. Returns the deleted
+    /// token.
+    pub fn delete_by_server_and_token(
+        conn: &PgConnection,
+        server_id: Uuid,
+        token: &str
+    ) -> Result<Option<Self>, ApiError> {
+        let res = diesel::delete(
+            fast_token::table
+                .filter(fast_token::server_id.eq(server_id))
+                .filter(fast_token::token.eq(token))
+        )
+            .get_result(conn)
+            .optional()?;
+
+        Ok(res)
+    }
+
+    pub fn delete_by_token(conn: &PgConnection, token: &str) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            fast_token::table
+                .filter(fast_token::token.eq(token))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+
+    pub fn delete_expired(
+        conn: &PgConnection,
+        now: NaiveDateTime
+    ) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            fast_token::table
+                .filter(fast_token::expires_at.le(now))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+}
+
+#[derive(Serialize, Deserialize, Queryable, Insertable)]
+#[table_name = "fast_token_redemption"]
+pub struct FastTokenRedemption {
+    pub id: Uuid,
+    /// the redeemed token. `None` after the token was deleted.
+    pub fast_token_id: Option<Uuid>,
+    /// the code and the server of the token. They are kept after the token
+    /// was deleted.
+    pub token: String,
+    pub server_id: Uuid,
+    pub client: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl FastTokenRedemption {
+    pub fn find_by_fast_token(conn: &PgConnection, fast_token_id: Uuid) -> Result<Vec<Self>, ApiError> {
+        let result = fast_token_redemption::table
+            .filter(fast_token_redemption::fast_token_id.eq(Some(fast_token_id)))
+            .order(fast_token_redemption::created_at)
+            .load::<FastTokenRedemption>(conn)?;
+
+        Ok(result)
+    }
+
+    /// Finds the redemptions of the deleted tokens of the server with this
+    /// code.
+    pub fn find_deleted(
+        conn: &PgConnection,
+        server_id: Uuid,
+        token: &str
+    ) -> Result<Vec<Self>, ApiError> {
+        let result = fast_token_redemption::table
+            .filter(fast_token_redemption::server_id.eq(server_id))
+            .filter(fast_token_redemption::token.eq(token))
+            .filter(fast_token_redemption::fast_token_id.is_null())
+            .order(fast_token_redemption::created_at)
+            .load::<FastTokenRedemption>(conn)?;
+
+        Ok(result)
+    }
+
+    pub fn delete_before(conn: &PgConnection, limit: NaiveDateTime) -> Result<usize, ApiError> {
+        let res = diesel::delete(
+            fast_token_redemption::table
+                .filter(fast_token_redemption::created_at.lt(limit))
+        ).execute(conn)?;
+
+        Ok(res)
+    }
+
+    pub fn create(conn: &PgConnection, entry: Self) -> Result<Self, ApiError> {
+        let result = diesel::insert_into(fast_token_redemption::table)
+            .values(entry)
+            .get_result(conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(Serialize, Deserialize, AsChangeset, Queryable, Insertable)]
+#[table_name = "auth_token"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct AuthToken {
+    pub id: Uuid,
+    pub name: Option<String>,
+    /// the SHA-256 digest of the token
+    pub digest: String,
+    /// comma separated list of the allowed games. `None` allows all games.
+    pub games: Option<String>,
+    pub developer: bool,
+    pub fallback: bool,
+    pub fast_tokens: bool,
+    pub disabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    /// the SHA-256 digest of the signing key. It identifies the key of signed
+    /// requests. `None` if the token cannot sign requests.
+    pub key_id: Option<String>,
+    /// the signing key encrypted with `SIGNING_KEY_SECRET`. It must never be
+    /// sent to a client.
+    #[serde(skip)]
+    pub signing_key: Option<String>,
+}
+
+impl AuthToken {
+    pub fn find_all(conn: &PgConnection) -> Result<Vec<Self>, ApiError> {
+        let tokens = auth_token::table
+            .order(auth_token::created_at)
+            .load::<AuthToken>(conn)?;
+
+        Ok(tokens)
+    }
+
+    pub fn find_by_id(conn: &PgConnection, id: Uuid) -> Result<Self, ApiError> {
+        let token = auth_token::table
+            .filter(auth_token::id.eq(id))
+            .first(conn)?;
+
+        Ok(token)
+    }
+
+    /// Finds the enabled token with this digest.
+    pub fn find_by_digest(conn: &PgConnection, digest: &str) -> Result<Self, ApiError> {
+        let token = auth_token::table
+            .filter(auth_token::digest.eq(digest))
+            .filter(auth_token::disabled.eq(false))
+            .first(conn)?;
+
+        Ok(token)
+    }
+
+    /// Finds the enabled token with this key id.
+    pub fn find_by_key_id(conn: &PgConnection, key_id: &str) -> Result<Self, ApiError> {
+        let token = auth_token::table
+            .filter(auth_token::key_id.eq(key_id))
+            .filter(auth_token::disabled.eq(false))
+            .first(conn)?;
+
+        Ok(token)
+    }
+
+    pub fn create(conn: &PgConnection, token: Self) -> Result<Self, ApiError> {
+        let token = diesel::insert_into(auth_token::table)
+            .values(token)
+            .get_result(conn)?;
+
+        Ok(token)
+    }
+
+    pub fn update(conn: &PgConnection, token: Self) -> Result<Self, ApiError> {
+        let token = diesel::update(auth_token::table)
+            .filter(auth_token::id.eq(token.id))
+            .set(token)
+            .get_result(conn)?;
+
+        Ok(token)
+    }
 }
@@ -9,20 +9,36 @@ use actix_web::{App, HttpServer };
 use listenfd::ListenFd;
 use std::env;
 
+mod admin;
 mod api_error;
+mod config;
 mod db;
+mod rate_limit;
+mod reaper;
 mod schema;
+mod signing;
+mod tls;
 mod v1;
 mod tokens;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
+    if env::args().nth(1).as_deref() == Some("generate-token") {
+        let (token, line) = tokens::generate();
+        println!("token: {}", token);
+        println!("line:  {}", line);
+        return Ok(());
+    }
+
     println!("init pronto server ...");
     dotenv::dotenv()
         .ok();
     env_logger::init();
     db::init();
     tokens::init();
+    config::init();
+    v1::selection::init();
+    v1::fast_token::init();
 
     let mut listenfd = ListenFd::from_env();
     let mut server = HttpServer::new(|| {
@@ -33,10 +49,13 @@ async fn main() -> std::io::Result<()> {
             .max_age(3600);
 
         App::new()
+            .wrap(rate_limit::RateLimit)
             .wrap(cors)
             .configure(v1::init_routes)
-    });
-    
+            .configure(admin::init_routes)
+    })
+        .on_connect(tls::on_connect);
+
     server = match listenfd.take_tcp_listener(0)? {
         Some(listener) => server.listen(listener)?,
         None => {
@@ -47,6 +66,59 @@ async fn main() -> std::io::Result<()> {
         },
     };
 
+    if let Some(config) = tls::server_config() {
+        let host = env::var("HOST").expect("HOST not set");
+        let port = env::var("TLS_PORT").expect("TLS_PORT not set");
+        server = server.bind_rustls(format!("{}:{}", host, port), config)?;
+        info!("listening for TLS on port {}", port);
+    }
+
+    reaper::init();
+    tokens::watch();
+
     info!("starting server");
-    server.run().await
+    let server = server
+        // actix stops listening for signals after the first one. The `SIGHUP`
+        // to reload the tokens would disable the shutdown.
+        .disable_signals()
+        .run();
+    handle_signals(server.clone());
+    server.await
+}
+
+/// Stops the server on `SIGINT`, `SIGTERM` or `SIGQUIT`.
+fn handle_signals(server: actix_web::dev::Server) {
+    #[cfg(unix)]
+    {
+        use actix_rt::signal::unix::{signal, SignalKind};
+        let signals = [
+            (SignalKind::interrupt(), "SIGINT", false),
+            (SignalKind::terminate(), "SIGTERM", true),
+            (SignalKind::quit(), "SIGQUIT", false),
+        ];
+        for (kind, name, graceful) in signals {
+            let server = server.clone();
+            let mut stream = match signal(kind) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("cannot listen for {}: {}", name, e);
+                    continue;
+                }
+            };
+            actix_rt::spawn(async move {
+                if stream.recv().await.is_some() {
+                    info!("{} received, stopping", name);
+                    server.stop(graceful).await;
+                }
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    actix_rt::spawn(async move {
+        if actix_rt::signal::ctrl_c().await.is_ok() {
+            info!("SIGINT received, stopping");
+            server.stop(false).await;
+        }
+    });
 }
@@ -0,0 +1,172 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::http::Method;
+use actix_web::{Error, HttpResponse};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::future::{Future, Ready, ready};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The routes that share a limit.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Group {
+    /// The lookup of fast tokens with `GET /v1/token/{token}`
+    Lookup,
+    /// The public routes that search for servers
+    Matchmaking,
+    /// The routes that are used by the game servers
+    Server,
+}
+
+impl Group {
+    fn of(req: &ServiceRequest) -> Option<Group> {
+        let path = req.path();
+        if path.starts_with("/v1/token/") {
+            if req.method() == Method::GET && !path.ends_with("/redemptions") {
+                Some(Group::Lookup)
+            } else {
+                Some(Group::Server)
+            }
+        } else if path == "/v1/new" || path == "/v1/list" || path.starts_with("/v1/info/") {
+            Some(Group::Matchmaking)
+        } else if path.starts_with("/v1/update") || path == "/v1/heartbeat" || path == "/v1/token" {
+            Some(Group::Server)
+        } else {
+            None
+        }
+    }
+
+    /// The number of requests per minute. `0` disables the limit.
+    fn limit(self) -> u32 {
+        match self {
+            Group::Lookup => crate::config::rate_limit_lookup(),
+            Group::Matchmaking => crate::config::rate_limit_matchmaking(),
+            Group::Server => crate::config::rate_limit_server(),
+        }
+    }
+}
+
+/// A token bucket that holds up to `limit` requests and is refilled within a
+/// minute.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<(Group, String), Bucket>> = Mutex::new(HashMap::new());
+}
+
+/// Takes a request from the bucket of the client. Returns the number of seconds
+/// until the next request is allowed if the bucket is empty.
+fn take(group: Group, client: String) -> Result<(), u64> {
+    let limit = group.limit();
+    if limit == 0 {
+        return Ok(());
+    }
+    let limit = limit as f64;
+    let rate = limit / 60.0;
+    let now = Instant::now();
+
+    let mut buckets = BUCKETS.lock().expect("rate limit buckets poisoned");
+    let bucket = buckets.entry((group, client))
+        .or_insert(Bucket { tokens: limit, updated: now });
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * rate).min(limit);
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+    }
+}
+
+/// Removes the buckets that are full again.
+pub fn prune() {
+    let now = Instant::now();
+    BUCKETS.lock()
+        .expect("rate limit buckets poisoned")
+        .retain(|_, bucket| now.duration_since(bucket.updated).as_secs() < 60);
+}
+
+/// The IP of the client. Behind a trusted proxy it is the right-most entry of
+/// `X-Forwarded-For`, because that one is added by the proxy and the entries
+/// before it can be set by the client.
+fn client(req: &ServiceRequest) -> Option<String> {
+    if crate::config::rate_limit_trust_proxy() {
+        let forwarded = req.headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .last()
+            .and_then(|x| parse_ip(x.trim()));
+        if let Some(ip) = forwarded {
+            return Some(ip.to_string());
+        }
+    }
+    req.peer_addr().map(|x| x.ip().to_string())
+}
+
+/// Parses an IP that may be followed by a port.
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value.parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|x| x.ip()))
+}
+
+/// Middleware that limits the requests per client IP. Requests over the limit
+/// are answered with `429 Too Many Requests` and a `Retry-After` header.
+pub struct RateLimit;
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let limited = match (Group::of(&req), client(&req)) {
+            (Some(group), Some(client)) => take(group, client)
+                .err()
+                .map(|retry_after| (group, retry_after)),
+            _ => None,
+        };
+        if let Some((group, retry_after)) = limited {
+            debug!("rate limit of {:?} exceeded by {:?}", group, req.peer_addr());
+            let response = HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after.to_string()))
+                .finish();
+            return Box::pin(ready(Ok(req.into_response(response))));
+        }
+        Box::pin(self.service.call(req))
+    }
+}
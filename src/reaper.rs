@@ -0,0 +1,75 @@
+use crate::api_error::ApiError;
+use crate::v1::model::{GameServer, ServerStatus};
+use std::time::Duration;
+
+/// Removes all offline servers that are not seen for longer than the retention
+/// period, the fast tokens of offline servers, all expired fast tokens and the
+/// redemptions that are older than their retention period.
+fn reap() -> Result<(), ApiError> {
+    crate::rate_limit::prune();
+
+    let now = chrono::Utc::now().naive_utc();
+    let conn = crate::db::connection()?;
+
+    // a server with a long heartbeat timeout can still be online after the
+    // retention period
+    let limit = now - chrono::Duration::seconds(crate::config::server_retention() as i64);
+    let servers = crate::db::model::Server::find_last_seen_before(&conn, limit)
+        .unwrap_or_else(|e| {
+            error!("cannot load servers to remove: {}", e);
+            Vec::new()
+        });
+    for (server, server_info) in servers {
+        if ServerStatus::of_server(&server, &server_info) != ServerStatus::Offline {
+            continue;
+        }
+        match GameServer::delete(server.id) {
+            Ok(()) => info!("removed server {} (last seen {})", server.id, server.last_seen),
+            Err(e) => error!("cannot remove server {}: {}", server.id, e),
+        }
+    }
+
+    // the status is computed from the server and its info, so that the games
+    // of each server don't have to be loaded
+    let offline = crate::db::model::Server::find_all_with_info(&conn)
+        .map(|servers| servers.iter()
+            .filter(|(server, info)| ServerStatus::of_server(server, info) == ServerStatus::Offline)
+            .map(|(server, _)| server.id)
+            .collect::<Vec<_>>()
+        )
+        .and_then(|ids| crate::db::model::FastToken::delete_by_servers(&conn, &ids));
+    match offline {
+        Ok(0) => {},
+        Ok(count) => info!("removed {} fast tokens of offline servers", count),
+        Err(e) => error!("cannot remove fast tokens of offline servers: {}", e),
+    }
+
+    match crate::db::model::FastToken::delete_expired(&conn, now) {
+        Ok(0) => {},
+        Ok(count) => info!("removed {} expired fast tokens", count),
+        Err(e) => error!("cannot remove expired fast tokens: {}", e),
+    }
+
+    let limit = now - chrono::Duration::seconds(crate::config::redemption_retention() as i64);
+    match crate::db::model::FastTokenRedemption::delete_before(&conn, limit) {
+        Ok(0) => {},
+        Ok(count) => info!("removed {} old fast token redemptions", count),
+        Err(e) => error!("cannot remove old fast token redemptions: {}", e),
+    }
+
+    Ok(())
+}
+
+pub fn init() {
+    info!("Initializing Reaper");
+    let period = Duration::from_secs(crate::config::reaper_interval() as u64);
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = reap() {
+                error!("reaper failed: {}", e);
+            }
+        }
+    });
+}
@@ -1,3 +1,20 @@
+table! {
+    auth_token (id) {
+        id -> Uuid,
+        name -> Nullable<Text>,
+        digest -> Text,
+        games -> Nullable<Text>,
+        developer -> Bool,
+        fallback -> Bool,
+        fast_tokens -> Bool,
+        disabled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+        key_id -> Nullable<Text>,
+        signing_key -> Nullable<Text>,
+    }
+}
+
 table! {
     fast_token (id) {
         id -> Uuid,
@@ -7,6 +24,20 @@ table! {
         lobby -> Text,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        expires_at -> Timestamp,
+        max_uses -> Nullable<Int4>,
+        uses -> Int4,
+    }
+}
+
+table! {
+    fast_token_redemption (id) {
+        id -> Uuid,
+        fast_token_id -> Nullable<Uuid>,
+        token -> Text,
+        server_id -> Uuid,
+        client -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
 
@@ -31,6 +62,18 @@ table! {
         game_info_id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        versions -> Nullable<Text>,
+    }
+}
+
+table! {
+    server_label (id) {
+        id -> Uuid,
+        key -> Text,
+        value -> Text,
+        server_info_id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
@@ -47,16 +90,23 @@ table! {
         server_id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        region -> Nullable<Text>,
+        heartbeat_timeout -> Nullable<Int4>,
     }
 }
 
 joinable!(fast_token -> server (server_id));
+joinable!(fast_token_redemption -> fast_token (fast_token_id));
 joinable!(server_game -> server_info (game_info_id));
 joinable!(server_info -> server (server_id));
+joinable!(server_label -> server_info (server_info_id));
 
 allow_tables_to_appear_in_same_query!(
+    auth_token,
     fast_token,
+    fast_token_redemption,
     server,
     server_game,
     server_info,
+    server_label,
 );
@@ -0,0 +1,82 @@
+use actix_web::HttpRequest;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+use crate::api_error::ApiError;
+use crate::tokens::Token;
+
+lazy_static! {
+    /// the nonces of the accepted requests with the time until they are kept
+    static ref NONCES: Mutex<HashMap<(String, String), i64>> = Mutex::new(HashMap::new());
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Result<Option<&'a str>, ApiError> {
+    match req.headers().get(name) {
+        Some(x) => x.to_str()
+            .map(Some)
+            .map_err(|_| ApiError::new(403, format!("invalid {} header", name))),
+        None => Ok(None),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+/// The text that is signed by the game server.
+fn message(method: &str, path: &str, timestamp: &str, nonce: &str, body: &[u8]) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method, path, timestamp, nonce, hex(&Sha256::digest(body))
+    )
+}
+
+/// Checks the signature of the request and returns the token that signed it.
+/// Requests without a `signature` header are not signed and return `None`.
+pub fn verify(req: &HttpRequest, body: &[u8]) -> Result<Option<Token>, ApiError> {
+    let signature = match header(req, "signature")? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let missing = |name: &str| ApiError::new(403, format!("missing {} header", name));
+    let key_id = header(req, "token-id")?.ok_or_else(|| missing("token-id"))?;
+    let timestamp = header(req, "timestamp")?.ok_or_else(|| missing("timestamp"))?;
+    let nonce = header(req, "nonce")?.ok_or_else(|| missing("nonce"))?;
+
+    let (token, key) = crate::tokens::find_signing_key(key_id)
+        .ok_or_else(|| ApiError::new(403, "unknown token-id".to_string()))?;
+    let path = req.uri()
+        .path_and_query()
+        .map(|x| x.as_str())
+        .unwrap_or_else(|| req.path());
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key)
+        .expect("hmac accepts all key sizes");
+    mac.update(message(req.method().as_str(), path, timestamp, nonce, body).as_bytes());
+    let expected = hex(&mac.finalize().into_bytes());
+    if !bool::from(expected.as_bytes().ct_eq(signature.to_ascii_lowercase().as_bytes())) {
+        return Err(ApiError::new(403, "invalid signature".to_string()));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let max_age = crate::config::signature_max_age() as i64;
+    let timestamp: i64 = timestamp.parse()
+        .map_err(|_| ApiError::new(403, "invalid timestamp".to_string()))?;
+    if (now - timestamp).abs() > max_age {
+        return Err(ApiError::new(403, "timestamp is too old".to_string()));
+    }
+
+    let mut nonces = NONCES.lock().expect("nonce cache poisoned");
+    nonces.retain(|_, until| *until >= now);
+    let entry = (key_id.to_string(), nonce.to_string());
+    if nonces.contains_key(&entry) {
+        return Err(ApiError::new(403, "nonce was already used".to_string()));
+    }
+    nonces.insert(entry, timestamp + max_age);
+
+    Ok(Some(token))
+}
@@ -0,0 +1,78 @@
+use actix_tls::accept::rustls::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::internal::pemfile;
+use rustls::{
+    AllowAnyAnonymousOrAuthenticatedClient, NoClientAuth, RootCertStore, ServerConfig, Session,
+};
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+/// The SHA-256 fingerprint of the client certificate of the connection.
+#[derive(Clone)]
+pub struct ClientCertificate(pub String);
+
+fn open(path: &str) -> BufReader<File> {
+    let file = File::open(path)
+        .unwrap_or_else(|e| panic!("cannot open {}: {}", path, e));
+    BufReader::new(file)
+}
+
+/// Creates the TLS config from `TLS_CERT` and `TLS_KEY`. Returns `None` if TLS
+/// is not configured. If `TLS_CLIENT_CA` is set the clients can authenticate
+/// with a certificate signed by this CA.
+pub fn server_config() -> Option<ServerConfig> {
+    let cert_path = env::var("TLS_CERT").ok()?;
+    let key_path = env::var("TLS_KEY").expect("TLS_KEY not set");
+
+    let certs = pemfile::certs(&mut open(&cert_path))
+        .expect("invalid certificate in TLS_CERT");
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(&key_path))
+        .expect("invalid key in TLS_KEY");
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(&key_path))
+            .expect("invalid key in TLS_KEY");
+    }
+    let key = keys.into_iter()
+        .next()
+        .expect("no private key in TLS_KEY");
+
+    let mut config = match env::var("TLS_CLIENT_CA") {
+        Ok(path) => {
+            let mut roots = RootCertStore::empty();
+            let (valid, _) = roots.add_pem_file(&mut open(&path))
+                .expect("invalid certificate in TLS_CLIENT_CA");
+            info!("{} client CAs are trusted", valid);
+            ServerConfig::new(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+        },
+        Err(_) => ServerConfig::new(NoClientAuth::new()),
+    };
+    config.set_single_cert(certs, key)
+        .expect("TLS_KEY doesn't match TLS_CERT");
+    Some(config)
+}
+
+/// Remembers the fingerprint of the verified client certificate for the
+/// requests of this connection.
+pub fn on_connect(connection: &dyn Any, extensions: &mut Extensions) {
+    let stream = match connection.downcast_ref::<TlsStream<TcpStream>>() {
+        Some(x) => x,
+        None => return,
+    };
+    let cert = stream.get_ref().1
+        .get_peer_certificates()
+        .and_then(|x| x.into_iter().next());
+    if let Some(cert) = cert {
+        extensions.insert(ClientCertificate(fingerprint(&cert.0)));
+    }
+}
+
+/// The hex encoded SHA-256 digest of a DER encoded certificate.
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der).iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
@@ -1,34 +1,667 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use actix_web::web;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use diesel::pg::PgConnection;
 use lazy_static::lazy_static;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use crate::api_error::ApiError;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
 use std::env;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
 
-type Store = Vec<String>;
+/// The secret of a line in the token file. Secrets starting with `$argon2`
+/// are salted hashes of a token, all others are the token itself. Plain tokens
+/// are only kept as their unsalted SHA-256 digest. This is deliberate: the
+/// digest is looked up without hashing each entry and it can't be reversed for
+/// long random tokens like the generated ones.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Entry {
+    Plain([u8; 32]),
+    Hashed(String),
+}
 
-lazy_static! {
-    static ref STORE: Store = {
-        let path = env::var("TOKEN_FILE").expect("Token file not set");
-        let file = File::open(path).expect("cannot open token file");
-        let mut store = Vec::new();
-        for line in io::BufReader::new(file).lines() {
-            if let Ok(x) = line {
-                if x.starts_with("#") || x.len() == 0 {
-                    continue;
-                }
-                store.push(x);
+impl Entry {
+    fn parse(secret: &str) -> Entry {
+        if secret.starts_with("$argon2") {
+            Entry::Hashed(secret.to_string())
+        } else {
+            Entry::Plain(digest(secret))
+        }
+    }
+
+    /// The key of this entry that is stored in the database instead of the
+    /// token. It never contains the token itself.
+    fn identity(&self) -> String {
+        match self {
+            Entry::Plain(x) => format!("sha256:{}", hex(x)),
+            Entry::Hashed(x) => x.clone(),
+        }
+    }
+}
+
+/// Verifies the token against an argon2 hash. Plain tokens need no
+/// verification, because they are looked up by their digest.
+fn verify_hash(hash: &str, token: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(token.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The permissions of a token. They are set with `key=value` attributes after
+/// the secret in the token file. Without attributes a token may do everything.
+#[derive(Clone)]
+pub struct Scope {
+    /// the display name of the token
+    pub name: Option<String>,
+    /// the games the servers of this token may host. `None` allows all games.
+    pub games: Option<HashSet<String>>,
+    /// if the token may register developer servers
+    pub developer: bool,
+    /// if the token may register fallback servers
+    pub fallback: bool,
+    /// if the token may create fast tokens with `/v1/token`
+    pub fast_tokens: bool,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope {
+            name: None,
+            games: None,
+            developer: true,
+            fallback: true,
+            fast_tokens: true,
+        }
+    }
+}
+
+impl Scope {
+    fn parse<'a>(attributes: impl Iterator<Item = &'a str>) -> Result<Scope, String> {
+        let mut scope = Scope::default();
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=')
+                .ok_or_else(|| format!("attribute {} has no value", attribute))?;
+            let flag = || value.parse::<bool>()
+                .map_err(|_| format!("attribute {} is no boolean", key));
+            match key {
+                "name" => scope.name = Some(value.to_string()),
+                "games" => scope.games = Some(value.split(',')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_string())
+                    .collect()
+                ),
+                "developer" => scope.developer = flag()?,
+                "fallback" => scope.fallback = flag()?,
+                "fast-tokens" => scope.fast_tokens = flag()?,
+                _ => return Err(format!("unknown attribute {}", key)),
+            }
+        }
+        Ok(scope)
+    }
+
+    fn denied(&self, reason: String) -> ApiError {
+        ApiError::new(403, match &self.name {
+            Some(name) => format!("token {} {}", name, reason),
+            None => format!("token {}", reason),
+        })
+    }
+
+    pub fn check_game(&self, game: &str) -> Result<(), ApiError> {
+        match &self.games {
+            Some(games) if !games.contains(game) =>
+                Err(self.denied(format!("is not allowed to host game {}", game))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_flags(&self, developer: bool, fallback: bool) -> Result<(), ApiError> {
+        if developer && !self.developer {
+            return Err(self.denied("is not allowed to register developer servers".to_string()));
+        }
+        if fallback && !self.fallback {
+            return Err(self.denied("is not allowed to register fallback servers".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn check_fast_tokens(&self) -> Result<(), ApiError> {
+        match self.fast_tokens {
+            true => Ok(()),
+            false => Err(self.denied("is not allowed to create fast tokens".to_string())),
+        }
+    }
+}
+
+/// An authorized token.
+#[derive(Clone)]
+pub struct Token {
+    /// the key under which the server of this token is stored in the database
+    pub identity: String,
+    pub scope: Scope,
+}
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<Entry, Scope>,
+    /// the already verified tokens by their digest. This avoids hashing the
+    /// token again for each request.
+    cache: RwLock<HashMap<[u8; 32], Token>>,
+    /// the hashed tokens by their id, which is the part of the token before
+    /// the first `.`
+    hashed: HashMap<String, String>,
+    /// the unhashed tokens with their signing keys by their key id
+    keys: HashMap<String, (Entry, [u8; 32])>,
+    /// the tokens by the SHA-256 fingerprints of their client certificates
+    certs: HashMap<String, Entry>,
+}
+
+impl Store {
+    fn read(path: &str) -> io::Result<Store> {
+        let file = File::open(path)?;
+        let mut store = Store::default();
+        for (number, line) in io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let secret = match parts.next() {
+                Some(x) if !x.starts_with('#') => x,
+                _ => continue,
+            };
+            let invalid = |e: String| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", number + 1, e)
+            );
+            let (certs, attributes): (Vec<_>, Vec<_>) = parts
+                .partition(|x| x.starts_with("cert="));
+            let (ids, attributes): (Vec<_>, Vec<_>) = attributes.into_iter()
+                .partition(|x| x.starts_with("id="));
+            let scope = Scope::parse(attributes.into_iter())
+                .map_err(invalid)?;
+            let entry = Entry::parse(secret);
+            match (&entry, ids.as_slice()) {
+                (Entry::Plain(_), []) => {
+                    if secret.len() < MIN_PLAIN_LENGTH {
+                        warn!(
+                            "line {}: the token is shorter than {} characters",
+                            number + 1, MIN_PLAIN_LENGTH
+                        );
+                    }
+                    let key = signing_key(secret);
+                    store.keys.insert(key_id(&key), (entry.clone(), key));
+                },
+                (Entry::Plain(_), _) =>
+                    return Err(invalid("only hashed tokens have an id".to_string())),
+                (Entry::Hashed(hash), [id]) => {
+                    let id = &id["id=".len()..];
+                    if store.hashed.insert(id.to_string(), hash.clone()).is_some() {
+                        return Err(invalid(format!("id {} is used twice", id)));
+                    }
+                },
+                (Entry::Hashed(_), _) =>
+                    return Err(invalid("hashed tokens need exactly one id attribute".to_string())),
             }
+            for cert in certs {
+                let fingerprint = cert["cert=".len()..]
+                    .replace(':', "")
+                    .to_lowercase();
+                store.certs.insert(fingerprint, entry.clone());
+            }
+            store.entries.insert(entry, scope);
+        }
+        Ok(store)
+    }
+
+    /// Finds a token that was already verified or that is stored unhashed.
+    /// This is cheap, because it only looks up the digest of the token.
+    fn find_plain(&self, token_digest: &[u8; 32]) -> Option<Token> {
+        if let Some(token) = self.cache.read()
+            .expect("token cache poisoned")
+            .get(token_digest)
+        {
+            return Some(token.clone());
+        }
+        self.entries.get_key_value(&Entry::Plain(*token_digest))
+            .map(|(entry, scope)| Token {
+                identity: entry.identity(),
+                scope: scope.clone(),
+            })
+    }
+
+    /// Verifies the token against the hash with the id of the token. Hashed
+    /// tokens are found by their id, so that at most one hash is verified.
+    fn find_hashed(&self, token: &str, token_digest: &[u8; 32]) -> Option<Token> {
+        let (id, _) = token.split_once('.')?;
+        let hash = self.hashed.get(id)?;
+        if !verify_hash(hash, token) {
+            return None;
         }
-        info!("{} servers are authorized", store.len());
-        store
+        let entry = Entry::Hashed(hash.clone());
+        let token = Token {
+            identity: entry.identity(),
+            scope: self.entries.get(&entry)?.clone(),
+        };
+        self.cache.write()
+            .expect("token cache poisoned")
+            .insert(*token_digest, token.clone());
+        Some(token)
+    }
+
+    fn find(&self, token: &str) -> Option<Token> {
+        let token_digest = digest(token);
+        self.find_plain(&token_digest)
+            .or_else(|| self.find_hashed(token, &token_digest))
+    }
+}
+
+/// Plain tokens shorter than this are logged as a warning, because their
+/// unsalted digest could be brute-forced.
+const MIN_PLAIN_LENGTH: usize = 32;
+
+lazy_static! {
+    static ref PATH: Option<String> = env::var("TOKEN_FILE").ok();
+    static ref ADMIN_PATH: Option<String> = env::var("ADMIN_TOKEN_FILE").ok();
+
+    static ref STORE: RwLock<Arc<Store>> = {
+        let store = match PATH.as_deref() {
+            Some(path) => Store::read(path).expect("cannot open token file"),
+            None => Store::default(),
+        };
+        info!("{} servers are authorized by the token file", store.entries.len());
+        RwLock::new(Arc::new(store))
+    };
+
+    static ref ADMIN_STORE: RwLock<Arc<Store>> = {
+        let store = match ADMIN_PATH.as_deref() {
+            Some(path) => Store::read(path).expect("cannot open admin token file"),
+            None => Store::default(),
+        };
+        info!("{} admins are authorized", store.entries.len());
+        RwLock::new(Arc::new(store))
     };
 }
 
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+fn modified() -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &str| std::fs::metadata(path)
+        .and_then(|x| x.modified())
+        .ok();
+    (PATH.as_deref().and_then(modified), ADMIN_PATH.as_deref().and_then(modified))
+}
+
+fn store() -> Arc<Store> {
+    STORE.read()
+        .expect("token store poisoned")
+        .clone()
+}
+
 pub fn init() {
     info!("Initializing Tokens");
     lazy_static::initialize(&STORE);
+    lazy_static::initialize(&ADMIN_STORE);
+}
+
+/// Checks the token against the token file and the tokens in the database and
+/// returns its identity and permissions. The check runs on the blocking thread
+/// pool, because it may verify an argon2 hash and query the database.
+pub async fn find(token: String) -> Option<Token> {
+    web::block(move || find_blocking(&token))
+        .await
+        .unwrap_or_else(|e| {
+            error!("cannot check token: {}", e);
+            None
+        })
+}
+
+fn find_blocking(token: &str) -> Option<Token> {
+    // the hashes in the token file are verified last, so that the tokens in
+    // the database don't have to wait for argon2
+    let store = store();
+    let token_digest = digest(token);
+    if let Some(token) = store.find_plain(&token_digest) {
+        return Some(token);
+    }
+    // the hashed tokens still work while the database is unavailable
+    let found = match crate::db::connection() {
+        Ok(conn) => crate::db::model::AuthToken::find_by_digest(&conn, &hex(&token_digest))
+            .ok()
+            .map(|x| Token {
+                identity: auth_identity(x.id),
+                scope: (&x).into(),
+            }),
+        Err(e) => {
+            error!("cannot check token in the database: {}", e);
+            None
+        }
+    };
+    found.or_else(|| store.find_hashed(token, &token_digest))
 }
 
-pub fn has_token(token: &str) -> bool {
-    STORE.iter()
-        .any(|x| x.as_str() == token)
-}
\ No newline at end of file
+/// The identity of a token in the database. It stays the same if the token
+/// is rotated.
+pub fn auth_identity(id: Uuid) -> String {
+    format!("auth:{}", id.to_simple())
+}
+
+/// The digest of the token that is stored in the database.
+pub fn digest_hex(token: &str) -> String {
+    hex(&digest(token))
+}
+
+/// The key that signs the requests of a token. It is derived from the token
+/// with a prefix, so that it differs from the digest that identifies the
+/// token.
+fn signing_key(token: &str) -> [u8; 32] {
+    Sha256::digest(format!("pronto-sign:{}", token).as_bytes()).into()
+}
+
+/// The key id and the encrypted signing key of a token in the database. Both
+/// are `None` without `SIGNING_KEY_SECRET`, so the token cannot sign requests.
+pub fn signing_columns(token: &str) -> (Option<String>, Option<String>) {
+    let secret = match crate::config::signing_key_secret() {
+        Some(x) => x,
+        None => return (None, None),
+    };
+    let key = signing_key(token);
+    let key_id = key_id(&key);
+    let nonce: [u8; 12] = rand::random();
+    let encrypted = cipher(secret)
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: &key, aad: key_id.as_bytes() })
+        .expect("cannot encrypt signing key");
+    let value = format!("{}{}", hex(&nonce), hex(&encrypted));
+    (Some(key_id), Some(value))
+}
+
+/// Decrypts a signing key of the database. The key id is authenticated with
+/// it, so a key cannot be moved to another token.
+fn decrypt_signing_key(value: &str, key_id: &str) -> Option<[u8; 32]> {
+    let secret = crate::config::signing_key_secret()?;
+    let value = unhex(value)?;
+    if value.len() < 12 {
+        return None;
+    }
+    let (nonce, encrypted) = value.split_at(12);
+    let key = cipher(secret)
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: encrypted, aad: key_id.as_bytes() })
+        .ok()?;
+    key.try_into().ok()
+}
+
+/// The cipher of the signing keys. Its key is the SHA-256 digest of the
+/// secret.
+fn cipher(secret: &str) -> Aes256Gcm {
+    Aes256Gcm::new(&Sha256::digest(secret.as_bytes()))
+}
+
+fn unhex(value: &str) -> Option<Vec<u8>> {
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The id of a signing key. Signed requests send it instead of the token.
+fn key_id(key: &[u8; 32]) -> String {
+    hex(&Sha256::digest(key))
+}
+
+/// Finds the token with the signing key of this id and returns it with the
+/// key. The key is the SHA-256 digest of `pronto-sign:` and the token. Tokens
+/// that are stored as argon2 hashes cannot sign requests.
+pub fn find_signing_key(key_id: &str) -> Option<(Token, [u8; 32])> {
+    let store = store();
+    if let Some((entry, key)) = store.keys.get(key_id) {
+        let scope = store.entries.get(entry)?.clone();
+        return Some((
+            Token {
+                identity: entry.identity(),
+                scope,
+            },
+            *key,
+        ));
+    }
+    let conn = crate::db::connection().ok()?;
+    let token = crate::db::model::AuthToken::find_by_key_id(&conn, key_id).ok()?;
+    let key = decrypt_signing_key(token.signing_key.as_deref()?, key_id)?;
+    Some((
+        Token {
+            identity: auth_identity(token.id),
+            scope: (&token).into(),
+        },
+        key,
+    ))
+}
+
+impl From<&crate::db::model::AuthToken> for Scope {
+    fn from(value: &crate::db::model::AuthToken) -> Self {
+        Scope {
+            name: value.name.clone(),
+            games: value.games.as_ref().map(|x| x.split(',')
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect()
+            ),
+            developer: value.developer,
+            fallback: value.fallback,
+            fast_tokens: value.fast_tokens,
+        }
+    }
+}
+
+/// Finds the token of a client certificate. The certificates are assigned to
+/// the tokens with the `cert` attribute in the token file.
+pub fn find_by_certificate(fingerprint: &str) -> Option<Token> {
+    let store = store();
+    let entry = store.certs.get(fingerprint)?;
+    Some(Token {
+        identity: entry.identity(),
+        scope: store.entries.get(entry)?.clone(),
+    })
+}
+
+/// Checks the token against the admin token file. The admin tokens are
+/// separate from the tokens of the game servers.
+pub async fn find_admin(token: String) -> Option<Token> {
+    let store = ADMIN_STORE.read()
+        .expect("token store poisoned")
+        .clone();
+    web::block(move || store.find(&token))
+        .await
+        .unwrap_or_else(|e| {
+            error!("cannot check admin token: {}", e);
+            None
+        })
+}
+
+/// Hides most of the token identity. The rest is enough to tell the tokens
+/// apart.
+pub fn mask(identity: &str) -> String {
+    if identity.len() <= 16 || !identity.is_ascii() {
+        return "****".to_string();
+    }
+    format!("{}...{}", &identity[..10], &identity[identity.len() - 6..])
+}
+
+/// Returns the display name of the token with this identity.
+pub fn name_of(conn: &PgConnection, identity: &str) -> Option<String> {
+    if let Some(id) = identity.strip_prefix("auth:") {
+        let id = Uuid::parse_str(id).ok()?;
+        return crate::db::model::AuthToken::find_by_id(conn, id).ok()?.name;
+    }
+    store().entries.iter()
+        .find(|(entry, _)| entry.identity() == identity)
+        .and_then(|(_, scope)| scope.name.clone())
+}
+
+/// Creates a new random token.
+pub fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect()
+}
+
+/// Creates a new random token and the line for the token file with its hash.
+/// The token starts with a random id and a `.`. The id is stored next to the
+/// hash, so that the token can be checked without verifying all hashes.
+pub fn generate() -> (String, String) {
+    let id = new_token()[..8].to_string();
+    let token = format!("{}.{}", id, new_token());
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let hash = Argon2::default()
+        .hash_password(token.as_bytes(), &salt)
+        .expect("cannot hash token")
+        .to_string();
+    (token, format!("{} id={}", hash, id))
+}
+
+/// The name of the token or its masked identity for the logs.
+fn label(entry: &Entry, scope: &Scope) -> String {
+    match &scope.name {
+        Some(name) => name.clone(),
+        None => mask(&entry.identity()),
+    }
+}
+
+/// Reads the token files again and replaces the current tokens with them. The
+/// files are reloaded independently and a file that cannot be read keeps its
+/// current tokens. All servers that used a removed token are deregistered.
+/// Returns the number of authorized servers.
+pub fn reload() -> io::Result<usize> {
+    if let Some(path) = ADMIN_PATH.as_deref() {
+        match read_store(path, &ADMIN_STORE) {
+            Ok(admins) => {
+                info!("{} admins are authorized", admins.entries.len());
+                replace(&ADMIN_STORE, Arc::new(admins));
+            },
+            Err(e) => error!("cannot reload admin token file: {}", e),
+        }
+    }
+
+    let new = Arc::new(match PATH.as_deref() {
+        Some(path) => read_store(path, &STORE)?,
+        None => Store::default(),
+    });
+    let old = replace(&STORE, new.clone());
+    let added = new.entries.iter()
+        .filter(|(x, _)| !old.entries.contains_key(x))
+        .collect::<Vec<_>>();
+    let removed = old.entries.iter()
+        .filter(|(x, _)| !new.entries.contains_key(x))
+        .collect::<Vec<_>>();
+    if added.is_empty() && removed.is_empty() {
+        return Ok(new.entries.len());
+    }
+    info!(
+        "{} servers are authorized by the token file ({} added, {} removed)",
+        new.entries.len(), added.len(), removed.len()
+    );
+    for (entry, scope) in added {
+        info!("token {} was added", label(entry, scope));
+    }
+
+    for (entry, scope) in removed {
+        info!("token {} was removed", label(entry, scope));
+        if let Err(e) = revoke(&entry.identity(), scope.name.as_deref()) {
+            error!("cannot deregister server of revoked token: {}", e);
+        }
+    }
+    Ok(new.entries.len())
+}
+
+/// Reads a token file for a reload. A file of zero bytes is not applied while
+/// tokens are authorized, because it is most likely still being written. A
+/// file that only contains comments revokes all tokens.
+fn read_store(path: &str, current: &RwLock<Arc<Store>>) -> io::Result<Store> {
+    let truncated = std::fs::metadata(path)?.len() == 0;
+    if truncated && !current.read().expect("token store poisoned").entries.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the file is empty, the current tokens are kept"
+        ));
+    }
+    Store::read(path)
+}
+
+/// Swaps the tokens of the store and returns the previous ones.
+fn replace(store: &RwLock<Arc<Store>>, new: Arc<Store>) -> Arc<Store> {
+    let mut store = store.write().expect("token store poisoned");
+    std::mem::replace(&mut *store, new)
+}
+
+/// Deregisters the server of a token that is no longer authorized.
+pub fn revoke(identity: &str, name: Option<&str>) -> Result<(), ApiError> {
+    let conn = crate::db::connection()?;
+    if let Ok(server) = crate::db::model::Server::find_by_token(&conn, identity) {
+        crate::v1::model::GameServer::delete(server.id)?;
+        match name {
+            Some(name) => info!("removed server {} (token {} revoked)", server.id, name),
+            None => info!("removed server {} (token revoked)", server.id),
+        }
+    }
+    Ok(())
+}
+
+/// Reloads the token files if they were changed or if the process receives a
+/// `SIGHUP`.
+pub fn watch() {
+    let period = Duration::from_secs(crate::config::token_watch_interval() as u64);
+    actix_rt::spawn(async move {
+        let mut last = modified();
+        let mut changed = false;
+        let mut interval = actix_rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            let current = modified();
+            if current != last {
+                // the file is reloaded once it stays unchanged for an
+                // interval, so that it isn't read while it is written
+                last = current;
+                changed = true;
+            } else if changed {
+                changed = false;
+                info!("token file changed");
+                if let Err(e) = reload() {
+                    error!("cannot reload token file: {}", e);
+                }
+            }
+        }
+    });
+
+    #[cfg(unix)]
+    actix_rt::spawn(async {
+        use actix_rt::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            info!("received SIGHUP");
+            if let Err(e) = reload() {
+                error!("cannot reload token file: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,253 @@
+use lazy_static::lazy_static;
+use rand::Rng;
+use rand::distributions::{Distribution, Uniform};
+use std::env;
+use std::fs;
+use uuid::Uuid;
+use crate::api_error::ApiError;
+use crate::config::FastTokenConfig;
+use crate::db::model::FastToken;
+
+/// Crockford's base 32 alphabet. It has no `I`, `L` and `O` that can be
+/// confused with `1` and `0` and no `U` to avoid accidental obscenity.
+pub const CROCKFORD_ALPHABET: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of random codes that are tried before the creation of a fast
+/// token fails.
+const MAX_ATTEMPTS: usize = 16;
+
+lazy_static! {
+    /// The words that must not be part of a fast token.
+    static ref BLOCKLIST: Vec<String> = {
+        let content = match env::var("FAST_TOKEN_BLOCKLIST") {
+            Ok(path) => fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("cannot read {}: {}", path, e)),
+            Err(_) => include_str!("../../resources/fast-token-blocklist.txt").to_string(),
+        };
+        let words: Vec<String> = content.lines()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty() && !x.starts_with('#'))
+            .map(|x| x.to_uppercase())
+            .collect();
+        info!("{} words are blocked in fast tokens", words.len());
+        words
+    };
+}
+
+pub fn init() {
+    lazy_static::initialize(&BLOCKLIST);
+}
+
+/// Replaces the digits that look like letters, so that the blocklist also
+/// matches tokens like `5H1T`.
+fn as_letters(token: &str) -> String {
+    token.chars()
+        .map(|x| match x {
+            '0' => 'O',
+            '1' => 'I',
+            '3' => 'E',
+            '4' => 'A',
+            '5' => 'S',
+            '7' => 'T',
+            '8' => 'B',
+            x => x,
+        })
+        .collect()
+}
+
+/// Checks if the token contains a word of the blocklist.
+fn is_blocked(token: &str) -> bool {
+    let letters = as_letters(token);
+    BLOCKLIST.iter()
+        .any(|word| token.contains(word.as_str()) || letters.contains(word.as_str()))
+}
+
+/// Creates a random token. Returns `None` if it contains a word of the
+/// blocklist.
+pub fn generate<R: Rng>(rng: &mut R, alphabet: &[char], length: usize) -> Option<String> {
+    let dist = Uniform::new(0, alphabet.len());
+    let token: String = (0..length)
+        .map(|_| alphabet[dist.sample(rng)])
+        .collect();
+    if is_blocked(&token) {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// Stores the entry with a new random code. Each code is reserved atomically
+/// by the database, so concurrent requests never get the same code. Expired
+/// tokens are replaced. Fails with `503` if no free code was found in
+/// `MAX_ATTEMPTS` tries. Codes with a blocked word count as tries.
+pub fn allocate(config: &FastTokenConfig, entry: FastToken) -> Result<FastToken, ApiError> {
+    let mut rng = rand::thread_rng();
+    for _ in 0..MAX_ATTEMPTS {
+        let token = match generate(&mut rng, &config.alphabet, config.length) {
+            Some(x) => x,
+            None => continue,
+        };
+        let candidate = FastToken {
+            id: Uuid::new_v4(),
+            token,
+            ..entry.clone()
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let result = crate::db::transaction(|conn| FastToken::try_create(conn, candidate, now))?;
+        if let Some(token) = result {
+            return Ok(token);
+        }
+    }
+    warn!("no free fast token for {} in {} attempts", entry.game, MAX_ATTEMPTS);
+    Err(ApiError::new(503, "no free fast token available, try again later".to_string()))
+}
+
+/// Brings the user input into the form of the stored tokens. Separators and
+/// whitespace are removed and the letters are upper-cased.
+pub fn normalize(token: &str) -> String {
+    token.chars()
+        .filter(|x| *x != '-' && !x.is_whitespace())
+        .flat_map(|x| x.to_uppercase())
+        .collect()
+}
+
+/// Replaces the characters that are not part of the Crockford alphabet with the
+/// digits they are confused with. Returns `None` if nothing was replaced.
+pub fn normalize_crockford(token: &str) -> Option<String> {
+    let result: String = token.chars()
+        .map(|x| match x {
+            'O' => '0',
+            'I' | 'L' => '1',
+            x => x,
+        })
+        .collect();
+    if result != token {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::model::Server;
+    use std::collections::HashSet;
+    use std::thread;
+
+    fn config(alphabet: &[char], length: usize) -> FastTokenConfig {
+        FastTokenConfig {
+            length,
+            alphabet: alphabet.to_vec(),
+            crockford: false,
+            ttl: 60,
+            min_ttl: 60,
+            max_ttl: 60,
+        }
+    }
+
+    fn entry(server_id: Uuid, now: chrono::NaiveDateTime) -> FastToken {
+        FastToken {
+            id: Uuid::new_v4(),
+            token: String::new(),
+            server_id,
+            game: "test".to_string(),
+            lobby: "test".to_string(),
+            created_at: now,
+            updated_at: None,
+            expires_at: now + chrono::Duration::seconds(60),
+            max_uses: None,
+            uses: 0,
+        }
+    }
+
+    /// All codes of the alphabet and length that contain no blocked word.
+    fn usable_codes(alphabet: &[char], length: usize) -> usize {
+        (0..length).fold(vec![String::new()], |codes, _| codes.iter()
+            .flat_map(|code| alphabet.iter().map(move |x| format!("{}{}", code, x)))
+            .collect()
+        )
+            .iter()
+            .filter(|x| !is_blocked(x))
+            .count()
+    }
+
+    #[test]
+    fn blocked_codes_count_as_attempts() {
+        // XXX is the only code and it is blocked, so the database is never
+        // reached
+        let config = config(&['X'], 3);
+        assert_eq!(usable_codes(&config.alphabet, config.length), 0);
+        let now = chrono::Utc::now().naive_utc();
+        let result = allocate(&config, entry(Uuid::new_v4(), now));
+        assert_eq!(result.err().map(|e| e.status_code), Some(503));
+    }
+
+    /// Needs a database in `DATABASE_URL` and is skipped without it.
+    #[test]
+    fn concurrent_allocation_never_reuses_codes() {
+        dotenv::dotenv().ok();
+        if env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL is not set, skipping the test");
+            return;
+        }
+        crate::db::init();
+        let now = chrono::Utc::now().naive_utc();
+        let server = crate::db::connection()
+            .and_then(|conn| Server::create(&conn, Server {
+                id: Uuid::new_v4(),
+                last_seen: now,
+                token: format!("test:{}", Uuid::new_v4()),
+                created_at: now,
+                updated_at: None,
+            }))
+            .expect("cannot create test server");
+        // two codes, so that the requests compete for them. With
+        // `MAX_ATTEMPTS` tries a request finds the last free code for sure.
+        let config = config(&['Q', 'R'], 1);
+        let usable = usable_codes(&config.alphabet, config.length);
+        assert_eq!(usable, 2);
+        let entry = entry(server.id, now);
+
+        let config = &config;
+        let entry = &entry;
+        let results: Vec<Result<FastToken, ApiError>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..40)
+                    .map(|_| allocate(config, entry.clone()))
+                    .collect::<Vec<_>>()
+                ))
+                .collect();
+            workers.into_iter()
+                .flat_map(|x| x.join().expect("worker panicked"))
+                .collect()
+        });
+
+        let mut codes = HashSet::new();
+        let mut exhausted = 0;
+        for result in results {
+            match result {
+                Ok(token) => assert!(codes.insert(token.token), "code was allocated twice"),
+                Err(e) => {
+                    assert_eq!(e.status_code, 503, "unexpected error: {}", e);
+                    exhausted += 1;
+                },
+            }
+        }
+        assert_eq!(codes.len(), usable);
+        assert_eq!(exhausted, 320 - usable);
+
+        // the expired codes are reused
+        let conn = crate::db::connection().expect("no connection");
+        for token in FastToken::find_all(&conn).expect("cannot load tokens") {
+            if token.server_id == server.id {
+                FastToken::update(&conn, FastToken { expires_at: now, ..token })
+                    .expect("cannot expire token");
+            }
+        }
+        allocate(config, entry.clone()).expect("expired codes were not reused");
+
+        FastToken::delete_by_server(&conn, server.id).expect("cannot clean up tokens");
+        Server::delete(&conn, server.id).expect("cannot clean up server");
+    }
+}
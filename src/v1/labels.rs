@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+}
+
+impl Requirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            Requirement::Equals(key, value) =>
+                labels.get(key) == Some(value),
+            Requirement::NotEquals(key, value) =>
+                labels.get(key) != Some(value),
+            Requirement::In(key, values) =>
+                matches!(labels.get(key), Some(x) if values.contains(x)),
+            Requirement::NotIn(key, values) =>
+                !matches!(labels.get(key), Some(x) if values.contains(x)),
+        }
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key_of = |key: &str| {
+            let key = key.trim();
+            if key.is_empty() {
+                Err(format!("missing label key in {}", s))
+            } else if key.contains(|x: char| x.is_whitespace() || "!<>=~".contains(x)) {
+                Err(format!("invalid label key {} in {}", key, s))
+            } else {
+                Ok(key.to_string())
+            }
+        };
+        let value_of = |value: &str| {
+            let value = value.trim();
+            if value.is_empty() {
+                Err(format!("missing label value in {}", s))
+            } else {
+                Ok(value.to_string())
+            }
+        };
+        if let Some(open) = s.find('(') {
+            let values = s[open + 1..].trim_end()
+                .strip_suffix(')')
+                .ok_or_else(|| format!("missing ) in {}", s))?
+                .split(',')
+                .map(value_of)
+                .collect::<Result<_, _>>()?;
+            let mut head = s[..open].split_whitespace();
+            let key = key_of(head.next().unwrap_or(""))?;
+            return match (head.next(), head.next()) {
+                (Some("in"), None) => Ok(Requirement::In(key, values)),
+                (Some("notin"), None) => Ok(Requirement::NotIn(key, values)),
+                _ => Err(format!("expected in or notin in {}", s)),
+            };
+        }
+        if let Some((key, value)) = s.split_once("!=") {
+            return Ok(Requirement::NotEquals(key_of(key)?, value_of(value)?));
+        }
+        if let Some((key, value)) = s.split_once("==").or_else(|| s.split_once('=')) {
+            return Ok(Requirement::Equals(key_of(key)?, value_of(value)?));
+        }
+        Err(format!("invalid label requirement {}", s))
+    }
+}
+
+/// A selector for the labels of a server. It is a comma separated list of
+/// requirements that all have to match, e.g.
+/// `tier=beta,mode!=casual,build in (1.4.1, 1.4.2),region notin (us)`.
+/// Requirements with `!=` and `notin` also match if the label is missing.
+#[derive(Default)]
+pub struct LabelSelector(Vec<Requirement>);
+
+impl LabelSelector {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.0.iter()
+            .all(|x| x.matches(labels))
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.matches('(').count() != s.matches(')').count() {
+            return Err(format!("unbalanced parentheses in {}", s));
+        }
+        let mut requirements = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        // split at all commas that are not part of a value set
+        for (pos, c) in s.char_indices().chain(std::iter::once((s.len(), ','))) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    let term = s[start..pos].trim();
+                    if !term.is_empty() {
+                        requirements.push(term.parse()?);
+                    }
+                    start = pos + 1;
+                },
+                _ => {},
+            }
+        }
+        Ok(LabelSelector(requirements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn matches(selector: &str, pairs: &[(&str, &str)]) -> bool {
+        selector.parse::<LabelSelector>()
+            .unwrap_or_else(|e| panic!("cannot parse {}: {}", selector, e))
+            .matches(&labels(pairs))
+    }
+
+    #[test]
+    fn equals() {
+        assert!(matches("tier=beta", &[("tier", "beta")]));
+        assert!(matches("tier==beta", &[("tier", "beta")]));
+        assert!(!matches("tier=beta", &[("tier", "stable")]));
+        assert!(!matches("tier=beta", &[]));
+    }
+
+    #[test]
+    fn not_equals() {
+        assert!(matches("tier!=beta", &[("tier", "stable")]));
+        assert!(matches("tier!=beta", &[]));
+        assert!(!matches("tier!=beta", &[("tier", "beta")]));
+    }
+
+    #[test]
+    fn value_sets() {
+        assert!(matches("build in (1.4.1,1.4.2)", &[("build", "1.4.2")]));
+        assert!(!matches("build in (1.4.1,1.4.2)", &[("build", "1.4.3")]));
+        assert!(!matches("build in (1.4.1)", &[]));
+        assert!(matches("region notin (us,eu)", &[("region", "asia")]));
+        assert!(matches("region notin (us,eu)", &[]));
+        assert!(!matches("region notin (us,eu)", &[("region", "eu")]));
+    }
+
+    #[test]
+    fn all_requirements_have_to_match() {
+        let selector = "tier=beta,build in (1.4.1,1.4.2),region notin (us)";
+        assert!(matches(selector, &[("tier", "beta"), ("build", "1.4.1"), ("region", "eu")]));
+        assert!(!matches(selector, &[("tier", "beta"), ("build", "1.4.1"), ("region", "us")]));
+        assert!(matches("", &[]));
+    }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        let pairs = [("tier", "beta"), ("build", "1.4.2"), ("mode", "ranked")];
+        assert!(matches(" tier = beta , mode != casual ", &pairs));
+        assert!(matches("build  in  ( 1.4.1 , 1.4.2 ) ", &pairs));
+        assert!(matches("tier==beta,,", &pairs));
+    }
+
+    #[test]
+    fn malformed_selectors_are_rejected() {
+        for selector in &[
+            "build in (1.4.1",
+            "build in 1.4.1)",
+            "build in (1.4.1))(",
+            "build in ()",
+            "build in (1.4.1,)",
+            "tier=",
+            "tier!= ",
+            "=beta",
+            "tier",
+            "tier~beta",
+            "tier >= 1",
+            "build within (1.4.1)",
+            "in (1.4.1)",
+        ] {
+            assert!(selector.parse::<LabelSelector>().is_err(), "{} was accepted", selector);
+        }
+    }
+}
@@ -1,4 +1,7 @@
+pub mod fast_token;
+mod labels;
 pub mod model;
-mod routes;
+pub(crate) mod routes;
+pub mod selection;
 
 pub use routes::init_routes;
\ No newline at end of file
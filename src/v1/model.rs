@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 
-use rand::prelude::Distribution;
-use serde::{Deserialize, Serialize};
+use semver::{Version, VersionReq};
+use diesel::pg::PgConnection;
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 use crate::api_error::ApiError;
 
@@ -15,13 +17,20 @@ pub struct GameServerInfo {
     pub maintenance: bool,
     #[serde(rename= "max-clients")]
     pub max_clients: Option<u32>,
+    pub region: Option<String>,
+    /// overrides the number of seconds after the last update in which this
+    /// server is considered online
+    #[serde(rename = "heartbeat-timeout")]
+    pub heartbeat_timeout: Option<u32>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
     pub games: Vec<GameServerEntry>,
 }
 
-impl TryFrom<crate::db::model::ServerInfo> for GameServerInfo {
+impl TryFrom<(&PgConnection, crate::db::model::ServerInfo)> for GameServerInfo {
     type Error = ApiError;
 
-    fn try_from(value: crate::db::model::ServerInfo) -> Result<Self, Self::Error> {
+    fn try_from((conn, value): (&PgConnection, crate::db::model::ServerInfo)) -> Result<Self, Self::Error> {
         Ok(GameServerInfo {
             name: value.name,
             uri: value.uri,
@@ -30,7 +39,13 @@ impl TryFrom<crate::db::model::ServerInfo> for GameServerInfo {
             full: value.full,
             maintenance: value.maintenance,
             max_clients: value.max_clients.map(|x| x as u32),
-            games: crate::db::model::ServerGame::find_by_info(value.id)?
+            region: value.region,
+            heartbeat_timeout: value.heartbeat_timeout.map(|x| x as u32),
+            labels: crate::db::model::ServerLabel::find_by_info(conn, value.id)?
+                .into_iter()
+                .map(|x| (x.key, x.value))
+                .collect(),
+            games: crate::db::model::ServerGame::find_by_info(conn, value.id)?
                 .iter()
                 .map(|x| x.into())
                 .collect(),
@@ -38,6 +53,49 @@ impl TryFrom<crate::db::model::ServerInfo> for GameServerInfo {
     }
 }
 
+impl GameServerInfo {
+    /// Checks if all version requirements of the games are valid.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        for game in &self.games {
+            game.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn game(&self, name: &str) -> Option<&GameServerEntry> {
+        self.games.iter()
+            .find(|x| x.name == name)
+    }
+
+    /// The number of seconds after the last update in which this server is
+    /// considered online.
+    pub fn heartbeat_timeout(&self) -> u32 {
+        self.heartbeat_timeout
+            .unwrap_or_else(crate::config::heartbeat_timeout)
+    }
+
+    /// The number of clients in all games of this server
+    pub fn clients(&self) -> u32 {
+        self.games.iter()
+            .map(|x| x.clients)
+            .sum()
+    }
+
+    /// Checks if the server can take another lobby of this game. This is false
+    /// if the game is not supported or the server or the game reached one of
+    /// its maximums.
+    pub fn has_capacity(&self, game: &str) -> bool {
+        let entry = match self.game(game) {
+            Some(x) => x,
+            None => return false,
+        };
+        if matches!(entry.max_rooms, Some(max) if entry.rooms >= max) {
+            return false;
+        }
+        !matches!(self.max_clients, Some(max) if self.clients() >= max)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GameServerEntry {
     pub name: String,
@@ -46,6 +104,33 @@ pub struct GameServerEntry {
     #[serde(rename = "max-rooms")]
     pub max_rooms: Option<u32>,
     pub clients: u32,
+    /// semver requirement for the client versions that this game supports
+    pub versions: Option<String>,
+}
+
+impl GameServerEntry {
+    /// Checks if the version requirement is valid.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(versions) = &self.versions {
+            VersionReq::parse(versions)
+                .map_err(|e| ApiError::new(400, format!(
+                    "invalid versions of game {}: {}", self.name, e
+                )))?;
+        }
+        Ok(())
+    }
+
+    /// Checks if a client with this version can play this game. Games without
+    /// a version requirement support all versions.
+    pub fn supports(&self, version: &Version) -> bool {
+        match &self.versions {
+            Some(versions) => match VersionReq::parse(versions) {
+                Ok(req) => req.matches(version),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
 }
 
 impl From<crate::db::model::ServerGame> for GameServerEntry {
@@ -56,6 +141,7 @@ impl From<crate::db::model::ServerGame> for GameServerEntry {
             rooms: value.rooms as u32,
             max_rooms: value.max_rooms.map(|x| x as u32),
             clients: value.clients as u32,
+            versions: value.versions,
         }
     }
 }
@@ -68,6 +154,7 @@ impl From<&crate::db::model::ServerGame> for GameServerEntry {
             rooms: value.rooms as u32,
             max_rooms: value.max_rooms.map(|x| x as u32),
             clients: value.clients as u32,
+            versions: value.versions.clone(),
         }
     }
 }
@@ -80,64 +167,326 @@ pub struct GameServer {
     #[serde(rename = "last-seen-sec")]
     pub last_seen_sec: f32,
     pub info: GameServerInfo,
+    pub utilisation: Utilisation,
+    pub status: ServerStatus,
+}
+
+/// The liveness of a server. A server is online until its heartbeat timeout is
+/// over. After that it is stale for another timeout and offline afterwards.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerStatus {
+    Online,
+    Stale,
+    Offline,
+}
+
+impl ServerStatus {
+    pub fn of(last_seen_sec: f32, info: &GameServerInfo) -> Self {
+        Self::with_timeout(last_seen_sec, info.heartbeat_timeout())
+    }
+
+    /// The status of a server that is only loaded from the database. This
+    /// avoids loading its games and labels.
+    pub fn of_server(server: &crate::db::model::Server, info: &crate::db::model::ServerInfo) -> Self {
+        let timeout = info.heartbeat_timeout
+            .map(|x| x as u32)
+            .unwrap_or_else(crate::config::heartbeat_timeout);
+        Self::with_timeout(last_seen_sec(server.last_seen), timeout)
+    }
+
+    fn with_timeout(last_seen_sec: f32, timeout: u32) -> Self {
+        let timeout = timeout as f32;
+        if last_seen_sec < timeout {
+            ServerStatus::Online
+        } else if last_seen_sec < 2.0 * timeout {
+            ServerStatus::Stale
+        } else {
+            ServerStatus::Offline
+        }
+    }
+}
+
+/// Utilisation figures that are derived from the reported server info. The
+/// ratios are only known if the server reports the corresponding maximum.
+#[derive(Serialize, Deserialize)]
+pub struct Utilisation {
+    pub clients: u32,
+    #[serde(rename = "clients-ratio")]
+    pub clients_ratio: Option<f32>,
+    pub games: BTreeMap<String, GameUtilisation>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameUtilisation {
+    #[serde(rename = "rooms-ratio")]
+    pub rooms_ratio: Option<f32>,
+    /// the highest of all known ratios for this game
+    pub utilisation: f32,
+    /// false if any maximum for this game is reached
+    pub available: bool,
+}
+
+impl From<&GameServerInfo> for Utilisation {
+    fn from(value: &GameServerInfo) -> Self {
+        let clients = value.clients();
+        let clients_ratio = ratio(clients, value.max_clients);
+        Utilisation {
+            clients,
+            clients_ratio,
+            games: value.games.iter()
+                .map(|game| {
+                    let rooms_ratio = ratio(game.rooms, game.max_rooms);
+                    (game.name.clone(), GameUtilisation {
+                        rooms_ratio,
+                        utilisation: rooms_ratio.into_iter()
+                            .chain(clients_ratio)
+                            .fold(0.0, f32::max),
+                        available: value.has_capacity(&game.name),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The load a server reports for a single game. Loads are compared by their
+/// utilisation first and by the absolute number of rooms and clients after that.
+#[derive(PartialEq, PartialOrd)]
+pub struct GameLoad {
+    pub utilisation: f32,
+    pub rooms: u32,
+    pub clients: u32,
+}
+
+fn ratio(value: u32, max: Option<u32>) -> Option<f32> {
+    match max {
+        Some(0) => Some(1.0),
+        Some(max) => Some(value as f32 / max as f32),
+        None => None,
+    }
 }
 
 impl GameServer {
+    /// Calculates the current load of this server for the given game. Returns
+    /// `None` if the game is not supported by this server.
+    pub fn load(&self, game: &str) -> Option<GameLoad> {
+        let entry = self.info.games.iter()
+            .find(|x| x.name == game)?;
+        let clients = self.info.clients();
+        let utilisation = ratio(entry.rooms, entry.max_rooms).into_iter()
+            .chain(ratio(clients, self.info.max_clients))
+            .fold(0.0, f32::max);
+        Some(GameLoad {
+            utilisation,
+            rooms: entry.rooms,
+            clients,
+        })
+    }
+
+    /// Removes the server with all its infos, games, labels and fast tokens.
+    pub fn delete(id: Uuid) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            crate::db::model::FastToken::delete_by_server(conn, id)?;
+            for info in crate::db::model::ServerInfo::find_all_by_server(conn, id)? {
+                crate::db::model::ServerLabel::delete_by_info(conn, info.id)?;
+                crate::db::model::ServerGame::delete_by_info(conn, info.id)?;
+                crate::db::model::ServerInfo::delete(conn, info.id)?;
+            }
+            crate::db::model::Server::delete(conn, id)?;
+            Ok(())
+        })
+    }
+
+    /// Marks the server as alive and updates the live counters of its games
+    /// without replacing the stored info.
+    pub fn heartbeat(id: Uuid, request: &HeartbeatRequest) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            crate::db::model::Server::touch(conn, id, now)?;
+            if let Some(games) = &request.games {
+                let info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+                for game in games {
+                    let count = crate::db::model::ServerGame::update_counters(
+                        conn,
+                        info.id,
+                        &game.name,
+                        game.rooms as i32,
+                        game.clients as i32
+                    )?;
+                    if count == 0 {
+                        return Err(ApiError::new(400, format!("unknown game {}", game.name)));
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Changes only the given fields of the stored info.
+    pub fn patch(id: Uuid, patch: GameServerInfoPatch) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let mut info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+            if let Some(name) = patch.name {
+                info.name = name;
+            }
+            if let Some(uri) = patch.uri {
+                info.uri = uri;
+            }
+            if let Some(developer) = patch.developer {
+                info.developer = developer;
+            }
+            if let Some(fallback) = patch.fallback {
+                info.fallback = fallback;
+            }
+            if let Some(full) = patch.full {
+                info.full = full;
+            }
+            if let Some(maintenance) = patch.maintenance {
+                info.maintenance = maintenance;
+            }
+            if let Some(max_clients) = patch.max_clients {
+                info.max_clients = max_clients.map(|x| x as i32);
+            }
+            if let Some(region) = patch.region {
+                info.region = region;
+            }
+            if let Some(heartbeat_timeout) = patch.heartbeat_timeout {
+                info.heartbeat_timeout = heartbeat_timeout.map(|x| x as i32);
+            }
+            info.updated_at = Some(now);
+            let info = crate::db::model::ServerInfo::update(conn, info)?;
+            if let Some(labels) = patch.labels {
+                crate::db::model::ServerLabel::delete_by_info(conn, info.id)?;
+                create_labels(conn, info.id, &labels, now)?;
+            }
+            crate::db::model::Server::touch(conn, id, now)?;
+            Ok(())
+        })
+    }
+
+    /// Adds the game to the stored info or replaces it if it already exists.
+    pub fn put_game(id: Uuid, game: GameServerEntry) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+            crate::db::model::ServerGame::delete_by_info_and_name(conn, info.id, &game.name)?;
+            create_game(conn, info.id, &game, now)?;
+            crate::db::model::Server::touch(conn, id, now)?;
+            Ok(())
+        })
+    }
+
+    /// Changes only the given fields of a single game of the stored info.
+    pub fn patch_game(id: Uuid, name: &str, patch: GameServerEntryPatch) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+            let mut game = crate::db::model::ServerGame::find_by_info_and_name(conn, info.id, name)
+                .map_err(|_| ApiError::new(404, format!("unknown game {}", name)))?;
+            if let Some(uri) = patch.uri {
+                game.uri = uri;
+            }
+            if let Some(rooms) = patch.rooms {
+                game.rooms = rooms as i32;
+            }
+            if let Some(max_rooms) = patch.max_rooms {
+                game.max_rooms = max_rooms.map(|x| x as i32);
+            }
+            if let Some(clients) = patch.clients {
+                game.clients = clients as i32;
+            }
+            if let Some(versions) = patch.versions {
+                game.versions = versions;
+            }
+            game.updated_at = Some(now);
+            crate::db::model::ServerGame::update(conn, game)?;
+            crate::db::model::Server::touch(conn, id, now)?;
+            Ok(())
+        })
+    }
+
+    /// Sets the maintenance flag without changing the last seen time. The
+    /// server overrides it with its next full update.
+    pub fn set_maintenance(id: Uuid, maintenance: bool) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let mut info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+            info.maintenance = maintenance;
+            info.updated_at = Some(chrono::Utc::now().naive_utc());
+            crate::db::model::ServerInfo::update(conn, info)?;
+            Ok(())
+        })
+    }
+
+    /// Removes a single game from the stored info.
+    pub fn delete_game(id: Uuid, name: &str) -> Result<(), ApiError> {
+        crate::db::transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let info = crate::db::model::ServerInfo::find_by_server(conn, id)?;
+            if crate::db::model::ServerGame::delete_by_info_and_name(conn, info.id, name)? == 0 {
+                return Err(ApiError::new(404, format!("unknown game {}", name)));
+            }
+            crate::db::model::Server::touch(conn, id, now)?;
+            Ok(())
+        })
+    }
+
+    /// Stores the server. `token` is the identity of the server token from
+    /// `crate::tokens::find`, never the token itself.
     pub fn save(&mut self, token: &str) -> Result<(), ApiError> {
         let now = chrono::Utc::now().naive_utc();
-        let id = 
-            if let Some(mut old) = crate::db::model::Server::find_by_token(token).ok() {
-            // if let Some(mut old) = GameServer::get_server(&self.id) {
-                let info = crate::db::model::ServerInfo::find_by_server(old.id)?;
-                crate::db::model::ServerGame::delete_by_info(info.id)?;
-                crate::db::model::ServerInfo::delete(info.id)?;
-                old.last_seen = now;
-                old.token = token.to_string();
-                old = crate::db::model::Server::update(old)?;
-                old.id
-            } else {
-                let mut entry = crate::db::model::Server {
-                    id: Uuid::new_v4(),
-                    last_seen: now,
-                    token: token.to_string(),
-                    created_at: now,
-                    updated_at: None,
+        let id = crate::db::transaction(|conn| {
+            let id = 
+                if let Some(mut old) = crate::db::model::Server::find_by_token(conn, token).ok() {
+                // if let Some(mut old) = GameServer::get_server(&self.id) {
+                    let info = crate::db::model::ServerInfo::find_by_server(conn, old.id)?;
+                    crate::db::model::ServerLabel::delete_by_info(conn, info.id)?;
+                    crate::db::model::ServerGame::delete_by_info(conn, info.id)?;
+                    crate::db::model::ServerInfo::delete(conn, info.id)?;
+                    old.last_seen = now;
+                    old.token = token.to_string();
+                    old = crate::db::model::Server::update(conn, old)?;
+                    old.id
+                } else {
+                    let mut entry = crate::db::model::Server {
+                        id: Uuid::new_v4(),
+                        last_seen: now,
+                        token: token.to_string(),
+                        created_at: now,
+                        updated_at: None,
+                    };
+                    entry = crate::db::model::Server::create(conn, entry)?;
+                    entry.id
                 };
-                entry = crate::db::model::Server::create(entry)?;
-                entry.id
-            };
         
-        let mut info = crate::db::model::ServerInfo {
-            id: Uuid::new_v4(),
-            name: self.info.name.clone(),
-            uri: self.info.uri.clone(),
-            developer: self.info.developer,
-            fallback: self.info.fallback,
-            full: self.info.full,
-            maintenance: self.info.maintenance,
-            max_clients: self.info.max_clients
-                .map(|x| x as i32),
-            server_id: id,
-            created_at: now,
-            updated_at: None,
-        };
-        info = crate::db::model::ServerInfo::create(info)?;
-
-        for game in &self.info.games {
-            crate::db::model::ServerGame::create(
-                crate::db::model::ServerGame {
-                    id: Uuid::new_v4(),
-                    name: game.name.clone(),
-                    uri: game.uri.clone(),
-                    rooms: game.rooms as i32,
-                    max_rooms: game.max_rooms.map(|x| x as i32),
-                    clients: game.clients as i32,
-                    game_info_id: info.id,
-                    created_at: now,
-                    updated_at: None,
-                }
-            )?;
-        }
+            let mut info = crate::db::model::ServerInfo {
+                id: Uuid::new_v4(),
+                name: self.info.name.clone(),
+                uri: self.info.uri.clone(),
+                developer: self.info.developer,
+                fallback: self.info.fallback,
+                full: self.info.full,
+                maintenance: self.info.maintenance,
+                max_clients: self.info.max_clients
+                    .map(|x| x as i32),
+                server_id: id,
+                created_at: now,
+                updated_at: None,
+                region: self.info.region.clone(),
+                heartbeat_timeout: self.info.heartbeat_timeout
+                    .map(|x| x as i32),
+            };
+            info = crate::db::model::ServerInfo::create(conn, info)?;
+
+            create_labels(conn, info.id, &self.info.labels, now)?;
+
+            for game in &self.info.games {
+                create_game(conn, info.id, game, now)?;
+            }
+
+            Ok(id)
+        })?;
 
         self.id = id.to_simple()
             .encode_lower(&mut Uuid::encode_buffer())
@@ -149,41 +498,87 @@ impl GameServer {
     }
 }
 
-impl TryFrom<crate::db::model::Server> for GameServer {
+fn create_labels(
+    conn: &PgConnection,
+    info_id: Uuid,
+    labels: &BTreeMap<String, String>,
+    now: chrono::NaiveDateTime
+) -> Result<(), ApiError> {
+    for (key, value) in labels {
+        crate::db::model::ServerLabel::create(
+            conn,
+            crate::db::model::ServerLabel {
+                id: Uuid::new_v4(),
+                key: key.clone(),
+                value: value.clone(),
+                server_info_id: info_id,
+                created_at: now,
+                updated_at: None,
+            }
+        )?;
+    }
+    Ok(())
+}
+
+fn create_game(
+    conn: &PgConnection,
+    info_id: Uuid,
+    game: &GameServerEntry,
+    now: chrono::NaiveDateTime
+) -> Result<(), ApiError> {
+    crate::db::model::ServerGame::create(
+        conn,
+        crate::db::model::ServerGame {
+            id: Uuid::new_v4(),
+            name: game.name.clone(),
+            uri: game.uri.clone(),
+            rooms: game.rooms as i32,
+            max_rooms: game.max_rooms.map(|x| x as i32),
+            clients: game.clients as i32,
+            game_info_id: info_id,
+            created_at: now,
+            updated_at: None,
+            versions: game.versions.clone(),
+        }
+    )?;
+    Ok(())
+}
+
+impl TryFrom<(&PgConnection, crate::db::model::Server)> for GameServer {
     type Error = ApiError;
 
-    fn try_from(value: crate::db::model::Server) -> Result<Self, Self::Error> {
-        Ok(GameServer {
-            id: Uuid::to_simple(value.id)
-                .encode_lower(&mut Uuid::encode_buffer())
-                .to_string(),
-            last_seen: chrono::NaiveDateTime::to_string(&value.last_seen),
-            last_seen_sec: chrono::Utc::now()
-                .naive_utc()
-                .signed_duration_since(value.last_seen)
-                .num_milliseconds() as f32
-                * 0.001,
-            info: crate::db::model::ServerInfo::find_by_server(value.id)?
-                .try_into()?
-        })
+    fn try_from((conn, value): (&PgConnection, crate::db::model::Server)) -> Result<Self, Self::Error> {
+        let info = crate::db::model::ServerInfo::find_by_server(conn, value.id)?;
+        (conn, value, info).try_into()
     }
 }
 
-impl TryFrom<(crate::db::model::Server, crate::db::model::ServerInfo)> for GameServer {
+/// The number of seconds since the last update of a server.
+fn last_seen_sec(last_seen: chrono::NaiveDateTime) -> f32 {
+    chrono::Utc::now()
+        .naive_utc()
+        .signed_duration_since(last_seen)
+        .num_milliseconds() as f32
+        * 0.001
+}
+
+impl TryFrom<(&PgConnection, crate::db::model::Server, crate::db::model::ServerInfo)> for GameServer {
     type Error = ApiError;
 
-    fn try_from((v1, v2): (crate::db::model::Server, crate::db::model::ServerInfo)) -> Result<Self, Self::Error> {
+    fn try_from(
+        (conn, v1, v2): (&PgConnection, crate::db::model::Server, crate::db::model::ServerInfo)
+    ) -> Result<Self, Self::Error> {
+        let info: GameServerInfo = (conn, v2).try_into()?;
+        let last_seen_sec = last_seen_sec(v1.last_seen);
         Ok(GameServer {
             id: Uuid::to_simple(v1.id)
                 .encode_lower(&mut Uuid::encode_buffer())
                 .to_string(),
             last_seen: chrono::NaiveDateTime::to_string(&v1.last_seen),
-            last_seen_sec: chrono::Utc::now()
-                .naive_utc()
-                .signed_duration_since(v1.last_seen)
-                .num_milliseconds() as f32
-                * 0.001,
-            info: v2.try_into()?
+            last_seen_sec,
+            utilisation: (&info).into(),
+            status: ServerStatus::of(last_seen_sec, &info),
+            info,
         })
     }
 }
@@ -193,6 +588,76 @@ pub struct UpdateResponse {
     pub id: String,
 }
 
+impl From<Uuid> for UpdateResponse {
+    fn from(value: Uuid) -> Self {
+        UpdateResponse {
+            id: value.to_simple()
+                .encode_lower(&mut Uuid::encode_buffer())
+                .to_string(),
+        }
+    }
+}
+
+/// Deserializes a field that is present. This keeps a `null` apart from a
+/// missing field for `Option<Option<T>>`.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameServerInfoPatch {
+    pub name: Option<String>,
+    pub uri: Option<String>,
+    pub developer: Option<bool>,
+    pub fallback: Option<bool>,
+    pub full: Option<bool>,
+    pub maintenance: Option<bool>,
+    #[serde(rename= "max-clients", default, deserialize_with = "present")]
+    pub max_clients: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub region: Option<Option<String>>,
+    #[serde(rename = "heartbeat-timeout", default, deserialize_with = "present")]
+    pub heartbeat_timeout: Option<Option<u32>>,
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameServerEntryPatch {
+    pub uri: Option<String>,
+    pub rooms: Option<u32>,
+    #[serde(rename = "max-rooms", default, deserialize_with = "present")]
+    pub max_rooms: Option<Option<u32>>,
+    pub clients: Option<u32>,
+    #[serde(default, deserialize_with = "present")]
+    pub versions: Option<Option<String>>,
+}
+
+impl GameServerEntryPatch {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(Some(versions)) = &self.versions {
+            VersionReq::parse(versions)
+                .map_err(|e| ApiError::new(400, format!("invalid versions: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct HeartbeatRequest {
+    pub games: Option<Vec<HeartbeatGame>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HeartbeatGame {
+    pub name: String,
+    pub rooms: u32,
+    pub clients: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ListQuery {
     #[serde(rename = "include-dev")]
@@ -201,6 +666,10 @@ pub struct ListQuery {
     pub include_fallback: Option<bool>,
     #[serde(rename = "exclude-full")]
     pub exclude_full: Option<bool>,
+    #[serde(rename = "include-offline")]
+    pub include_offline: Option<bool>,
+    /// label selector that the listed servers have to match
+    pub labels: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -213,6 +682,25 @@ pub struct NewRequest {
     pub developer: Option<bool>,
     pub fallback: Option<bool>,
     pub ignore: Option<Vec<String>>,
+    /// comma separated list of the preferred regions in the order of preference
+    pub region: Option<String>,
+    /// label selector that the selected server has to match
+    pub labels: Option<String>,
+    /// the version of the client
+    pub version: Option<String>,
+}
+
+impl NewRequest {
+    pub fn regions(&self) -> Vec<String> {
+        match &self.region {
+            Some(region) => region.split(',')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -228,61 +716,166 @@ pub struct NewResponse {
 pub struct FastTokenAddRequest {
     pub game: String,
     pub lobby: String,
+    /// the number of seconds the token is valid. Defaults to the ttl of the
+    /// game.
+    pub ttl: Option<u32>,
+    /// the number of times the token can be redeemed. Unlimited if not set.
+    #[serde(rename = "max-uses")]
+    pub max_uses: Option<u32>,
 }
 
-const MAX_FAST_TOKEN_SIZE: usize = 4;
-
 impl TryFrom<(Uuid, FastTokenAddRequest)> for crate::db::model::FastToken {
     type Error = ApiError;
 
     fn try_from((server_id, value): (Uuid, FastTokenAddRequest)) -> Result<Self, Self::Error> {
-        let now = chrono::Utc::now();
-        let limit = now.checked_sub_signed(
-            chrono::Duration::minutes(20)
-        )
-            .expect("limit traveled back in time")
-            .naive_utc();
-        let range = "ABCDEFGHIJKLMOPQRSTUVWXYZ0123456789";
-        let mut rng = rand::thread_rng();
-        let dist = rand::distributions::Uniform::new(0, range.len());
-        let mut token = String::with_capacity(MAX_FAST_TOKEN_SIZE);
-        loop {
-            token.clear();
-            for _ in 0..MAX_FAST_TOKEN_SIZE {
-                token.push(range.chars().nth(dist.sample(&mut rng))
-                    .expect("random out of range")
-                );
-            }
-            if let Err(_) = crate::db::model::FastToken::find_by_token_checked(&token, limit) {
-                return crate::db::model::FastToken::create(
-                    crate::db::model::FastToken {
-                        id: Uuid::new_v4(),
-                        token,
-                        server_id,
-                        game: value.game,
-                        lobby: value.lobby,
-                        created_at: now.naive_utc(),
-                        updated_at: None,
-                    }
-                );
-            }
+        let config = crate::config::fast_token(&value.game);
+        let ttl = value.ttl.unwrap_or(config.ttl);
+        if ttl < config.min_ttl || ttl > config.max_ttl {
+            return Err(ApiError::new(400, format!(
+                "ttl must be between {} and {} seconds",
+                config.min_ttl, config.max_ttl
+            )));
         }
+        if value.max_uses == Some(0) {
+            return Err(ApiError::new(400, "max-uses must be positive".to_string()));
+        }
+        let max_uses = value.max_uses
+            .map(|x| i32::try_from(x)
+                .map_err(|_| ApiError::new(400, "max-uses is too large".to_string()))
+            )
+            .transpose()?;
+        let now = chrono::Utc::now().naive_utc();
+        super::fast_token::allocate(config, crate::db::model::FastToken {
+            id: Uuid::new_v4(),
+            token: String::new(),
+            server_id,
+            game: value.game,
+            lobby: value.lobby,
+            created_at: now,
+            updated_at: None,
+            expires_at: now + chrono::Duration::seconds(ttl as i64),
+            max_uses,
+            uses: 0,
+        })
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct FastTokenAddResponse {
     pub token: String,
+    #[serde(rename = "expires-at")]
+    pub expires_at: String,
+    /// the number of seconds the token is valid
+    pub ttl: i64,
+    #[serde(rename = "max-uses")]
+    pub max_uses: Option<i32>,
 }
 
 impl From<crate::db::model::FastToken> for FastTokenAddResponse {
     fn from(value: crate::db::model::FastToken) -> Self {
         FastTokenAddResponse {
             token: value.token,
+            max_uses: value.max_uses,
+            expires_at: value.expires_at.to_string(),
+            ttl: value.expires_at
+                .signed_duration_since(value.created_at)
+                .num_seconds(),
+        }
+    }
+}
+
+/// A fast token in the list of the tokens of a server.
+#[derive(Serialize, Deserialize)]
+pub struct FastTokenEntry {
+    pub token: String,
+    pub game: String,
+    pub lobby: String,
+    pub uses: i32,
+    #[serde(rename = "max-uses")]
+    pub max_uses: Option<i32>,
+    #[serde(rename = "expires-at")]
+    pub expires_at: String,
+}
+
+impl From<crate::db::model::FastToken> for FastTokenEntry {
+    fn from(value: crate::db::model::FastToken) -> Self {
+        FastTokenEntry {
+            token: value.token,
+            game: value.game,
+            lobby: value.lobby,
+            uses: value.uses,
+            max_uses: value.max_uses,
+            expires_at: value.expires_at.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FastTokenFetchQuery {
+    /// optional information about the client that is stored with the
+    /// redemption
+    pub client: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FastTokenRedemption {
+    pub time: String,
+    pub client: Option<String>,
+}
+
+impl From<crate::db::model::FastTokenRedemption> for FastTokenRedemption {
+    fn from(value: crate::db::model::FastTokenRedemption) -> Self {
+        FastTokenRedemption {
+            time: value.created_at.to_string(),
+            client: value.client,
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct FastTokenRedemptionsResponse {
+    pub token: String,
+    pub uses: i32,
+    #[serde(rename = "max-uses")]
+    pub max_uses: Option<i32>,
+    /// `None` if the token was already deleted
+    #[serde(rename = "expires-at")]
+    pub expires_at: Option<String>,
+    pub redemptions: Vec<FastTokenRedemption>,
+}
+
+impl FastTokenRedemptionsResponse {
+    /// The redemptions of a token that was already deleted.
+    pub fn deleted(token: String, redemptions: Vec<crate::db::model::FastTokenRedemption>) -> Self {
+        FastTokenRedemptionsResponse {
+            token,
+            uses: redemptions.len() as i32,
+            max_uses: None,
+            expires_at: None,
+            redemptions: redemptions.into_iter()
+                .map(FastTokenRedemption::from)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<(&PgConnection, crate::db::model::FastToken)> for FastTokenRedemptionsResponse {
+    type Error = ApiError;
+
+    fn try_from((conn, value): (&PgConnection, crate::db::model::FastToken)) -> Result<Self, Self::Error> {
+        let redemptions = crate::db::model::FastTokenRedemption::find_by_fast_token(conn, value.id)?;
+        Ok(FastTokenRedemptionsResponse {
+            token: value.token,
+            uses: value.uses,
+            max_uses: value.max_uses,
+            expires_at: Some(value.expires_at.to_string()),
+            redemptions: redemptions.into_iter()
+                .map(FastTokenRedemption::from)
+                .collect(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct FastTokenFetchResponse {
     pub server: String,
@@ -294,12 +887,16 @@ pub struct FastTokenFetchResponse {
     pub game_uri: Option<String>,
 }
 
-impl TryFrom<crate::db::model::FastToken> for FastTokenFetchResponse {
+impl TryFrom<(&PgConnection, crate::db::model::FastToken)> for FastTokenFetchResponse {
     type Error = ApiError;
 
-    fn try_from(value: crate::db::model::FastToken) -> Result<Self, Self::Error> {
-        let server: GameServer = crate::db::model::Server::find_by_id(value.server_id)?
+    fn try_from((conn, value): (&PgConnection, crate::db::model::FastToken)) -> Result<Self, Self::Error> {
+        let server: GameServer = (conn, crate::db::model::Server::find_by_id(conn, value.server_id)?)
             .try_into()?;
+        // the tokens of a server that stopped sending heartbeats are invalid
+        if server.status == ServerStatus::Offline {
+            return Err(ApiError::new(404, "server is offline".to_string()));
+        }
         for game in &server.info.games {
             if game.name == value.game {
                 return Ok(FastTokenFetchResponse {
@@ -1,15 +1,23 @@
 // #[macro_use]
 // extern crate log;
 
+use std::cell::Cell;
 use std::convert::TryInto;
 
-use actix_web::{ HttpResponse, Responder, get, post, web};
+use actix_web::{ HttpResponse, Responder, delete, get, patch, post, put, web};
+use actix_web::http::StatusCode;
 use actix_files::NamedFile;
+use semver::Version;
+use serde::de::DeserializeOwned;
 use serde_json::json;
 use uuid::Uuid;
 use super::model::*;
+use super::labels::LabelSelector;
+use super::selection::SelectionStrategy;
+use crate::api_error::ApiError;
+use crate::tokens::{Scope, Token};
 
-fn get_header(req: &web::HttpRequest, name: &str) -> Option<String> {
+pub(crate) fn get_header(req: &web::HttpRequest, name: &str) -> Option<String> {
     Some(req.headers()
         .get(name)?
         .to_str()
@@ -77,21 +85,32 @@ async fn index_json() -> actix_web::Result<NamedFile> {
 }
 
 #[post("/v1/update")]
-async fn update(req: web::HttpRequest, request: web::Json<GameServerInfo>) -> impl Responder {
-    let token = match get_header(&req, "token") {
-        Some(token) => token,
-        None => return HttpResponse::Forbidden().finish(),
+async fn update(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let token = match authenticate(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request: GameServerInfo = match parse_body(&body) {
+        Ok(x) => x,
+        Err(response) => return response,
     };
-    if !crate::tokens::has_token(token.as_str()) {
-        return HttpResponse::Forbidden().finish();
+    if let Err(e) = check_scope(&token.scope, &request) {
+        return error_response(e);
+    }
+    if let Err(e) = request.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": e,
+        }));
     }
     let mut server = GameServer {
         id:  "".to_string(),
-        info: request.into_inner(),
+        utilisation: (&request).into(),
+        info: request,
         last_seen: "".to_string(),
         last_seen_sec: 0.0,
+        status: ServerStatus::Online,
     };
-    match server.save(&token.as_str()) {
+    match server.save(&token.identity) {
         Ok(()) =>
             HttpResponse::Ok().json(UpdateResponse {
                 id: server.id,
@@ -104,14 +123,210 @@ async fn update(req: web::HttpRequest, request: web::Json<GameServerInfo>) -> im
     
 }
 
+/// Checks if the token may register this server.
+fn check_scope(scope: &Scope, request: &GameServerInfo) -> Result<(), ApiError> {
+    scope.check_flags(request.developer, request.fallback)?;
+    for game in &request.games {
+        scope.check_game(&game.name)?;
+    }
+    Ok(())
+}
+
+/// Checks the signature of the request, the client certificate or the token in
+/// the request header.
+async fn authenticate(req: &web::HttpRequest, body: &[u8]) -> Result<Token, HttpResponse> {
+    match crate::signing::verify(req, body) {
+        Ok(Some(token)) => return Ok(token),
+        Ok(None) => {},
+        Err(err) => return Err(error_response(err)),
+    }
+    let cert = req.extensions()
+        .get::<crate::tls::ClientCertificate>()
+        .cloned();
+    if let Some(token) = cert.and_then(|x| crate::tokens::find_by_certificate(&x.0)) {
+        return Ok(token);
+    }
+    if crate::config::require_signature() {
+        return Err(error_response(ApiError::new(403, "request must be signed".to_string())));
+    }
+    let token = match get_header(req, "token") {
+        Some(x) => x,
+        None => return Err(HttpResponse::Forbidden().finish()),
+    };
+    crate::tokens::find(token)
+        .await
+        .ok_or_else(|| HttpResponse::Forbidden().finish())
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, HttpResponse> {
+    serde_json::from_slice(body)
+        .map_err(|e| error_response(ApiError::new(400, format!("invalid body: {}", e))))
+}
+
+/// Finds the registered server of the authenticated token.
+async fn authorized_server(
+    req: &web::HttpRequest,
+    body: &[u8]
+) -> Result<(crate::db::model::Server, Scope), HttpResponse> {
+    let token = authenticate(req, body).await?;
+    let conn = crate::db::connection().map_err(error_response)?;
+    crate::db::model::Server::find_by_token(&conn, &token.identity)
+        .map(|server| (server, token.scope))
+        .map_err(|_| HttpResponse::NotFound().finish())
+}
+
+pub(crate) fn error_response(err: ApiError) -> HttpResponse {
+    let status = StatusCode::from_u16(err.status_code)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status).json(json!({
+        "error": err,
+    }))
+}
+
+#[patch("/v1/update")]
+async fn update_patch(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let (server, scope) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request: GameServerInfoPatch = match parse_body(&body) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    if let Err(err) = scope.check_flags(
+        request.developer.unwrap_or(false),
+        request.fallback.unwrap_or(false)
+    ) {
+        return error_response(err);
+    }
+    match GameServer::patch(server.id, request) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/v1/update")]
+async fn deregister(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    match GameServer::delete(server.id) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
+#[put("/v1/update/games/{game}")]
+async fn game_put(
+    req: web::HttpRequest,
+    game: web::Path<String>,
+    body: web::Bytes
+) -> impl Responder {
+    let (server, scope) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request: GameServerEntry = match parse_body(&body) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    if let Err(err) = scope.check_game(&game) {
+        return error_response(err);
+    }
+    if request.name != *game {
+        return error_response(ApiError::new(400, "game name doesn't match the path".to_string()));
+    }
+    if let Err(err) = request.validate() {
+        return error_response(err);
+    }
+    match GameServer::put_game(server.id, request) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
+#[patch("/v1/update/games/{game}")]
+async fn game_patch(
+    req: web::HttpRequest,
+    game: web::Path<String>,
+    body: web::Bytes
+) -> impl Responder {
+    let (server, scope) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request: GameServerEntryPatch = match parse_body(&body) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    if let Err(err) = scope.check_game(&game) {
+        return error_response(err);
+    }
+    if let Err(err) = request.validate() {
+        return error_response(err);
+    }
+    match GameServer::patch_game(server.id, &game, request) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
+#[delete("/v1/update/games/{game}")]
+async fn game_delete(
+    req: web::HttpRequest,
+    game: web::Path<String>,
+    body: web::Bytes
+) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    match GameServer::delete_game(server.id, &game) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
+#[post("/v1/heartbeat")]
+async fn heartbeat(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    // the body is optional
+    let request: HeartbeatRequest = match body.is_empty() {
+        true => HeartbeatRequest::default(),
+        false => match parse_body(&body) {
+            Ok(x) => x,
+            Err(response) => return response,
+        },
+    };
+    match GameServer::heartbeat(server.id, &request) {
+        Ok(()) => HttpResponse::Ok().json(UpdateResponse::from(server.id)),
+        Err(err) => error_response(err),
+    }
+}
+
 #[get("/v1/list")]
 async fn list(query: web::Query<ListQuery>) -> impl Responder {
+    let labels = match parse_labels(&query.labels) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let entries = crate::db::snapshot(|conn| {
+        crate::db::model::Server::find_by_filter(
+            conn,
+            query.include_dev.unwrap_or(false),
+            query.include_fallback.unwrap_or(false),
+            query.exclude_full.unwrap_or(false)
+        )?
+            .into_iter()
+            .map(|(server, server_info)| (conn, server, server_info).try_into())
+            .collect::<Result<Vec<GameServer>, ApiError>>()
+    });
     let mut result = Vec::new();
-    for entry in match crate::db::model::Server::find_by_filter(
-        query.include_dev.unwrap_or(false),
-        query.include_fallback.unwrap_or(false),
-        query.exclude_full.unwrap_or(false)
-    ) {
+    for entry in match entries {
         Ok(x) => x,
         Err(e) => {
             return HttpResponse::InternalServerError().json(json!({
@@ -119,14 +334,12 @@ async fn list(query: web::Query<ListQuery>) -> impl Responder {
             }));
         }
     } {
-        result.push(match entry.try_into() {
-            Ok(x) => x,
-            Err(e) => {
-                return HttpResponse::InternalServerError().json(json!({
-                    "error": e,
-                }));
-            }
-        });
+        if entry.status == ServerStatus::Offline && !query.include_offline.unwrap_or(false) {
+            continue;
+        }
+        if labels.matches(&entry.info.labels) {
+            result.push(entry);
+        }
     }
 
     HttpResponse::Ok().json(ListResponse(result))
@@ -140,38 +353,54 @@ async fn info(server_id: web::Path<String>) -> impl Responder {
             return HttpResponse::NotFound().finish();
         },
     };
-    let info = match crate::db::model::Server::find_by_id(id) {
-        Ok(x) => x,
-        Err(_) => {
-            return HttpResponse::NotFound().finish();
-        },
-    };
-    let info: Result<GameServer, _> = info.try_into();
+    let info: Result<GameServer, _> = crate::db::snapshot(|conn| {
+        let server = crate::db::model::Server::find_by_id(conn, id)?;
+        (conn, server).try_into()
+    });
     match info {
         Ok(x) => HttpResponse::Ok().json(x),
+        Err(e) if e.status_code == 404 => HttpResponse::NotFound().finish(),
         Err(e) => HttpResponse::InternalServerError().json(json!({
             "error": e,
         }))
     }
 }
 
-async fn find_server(game: &str, dev: bool, fallback: bool, ignore: &Vec<String>) -> Option<GameServer> {
+/// The requirements of a new request that a server has to fulfill.
+struct Criteria<'a> {
+    game: &'a str,
+    ignore: &'a [String],
+    regions: Vec<String>,
+    labels: &'a LabelSelector,
+    version: Option<Version>,
+    /// set if a server was skipped only because it doesn't support the version
+    incompatible: Cell<bool>,
+}
+
+async fn find_server(
+    strategy: &dyn SelectionStrategy,
+    criteria: &Criteria<'_>,
+    dev: bool,
+    fallback: bool,
+) -> Option<GameServer> {
+    let game = criteria.game;
+    let mut candidates = Vec::new();
     // search for entries
-    for entry in match crate::db::model::Server
-        ::find_by_filter(dev, fallback, true) 
-    {
+    let entries = crate::db::snapshot(|conn| {
+        Ok(crate::db::model::Server::find_by_filter(conn, dev, fallback, true)?
+            .into_iter()
+            .filter_map(|(server, server_info)| (conn, server, server_info).try_into().ok())
+            .collect::<Vec<GameServer>>())
+    });
+    for entry in match entries {
         Ok(x) => x,
         Err(_) => {
             return None;
         },
     }
     {
-        let entry: GameServer = match entry.try_into() {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
         // check if server is ignored
-        if let Ok(_) = ignore.binary_search(&entry.id) {
+        if let Ok(_) = criteria.ignore.binary_search(&entry.id) {
             continue;
         }
         // check if entry flags matches filter
@@ -182,48 +411,75 @@ async fn find_server(game: &str, dev: bool, fallback: bool, ignore: &Vec<String>
         {
             continue;
         }
+        // check if entry labels matches the selector
+        if !criteria.labels.matches(&entry.info.labels) {
+            continue;
+        }
         // check if server is online
-        if entry.last_seen_sec >= 60.0 {
+        if entry.status != ServerStatus::Online {
             continue;
         }
-        // check if entry has game supported
-        for game_entry in &entry.info.games {
-            if game_entry.name == game {
-                return Some(entry);
-            }
+        // check if entry has game supported with the version of the client
+        match (entry.info.game(game), &criteria.version) {
+            (None, _) => continue,
+            (Some(x), Some(version)) if !x.supports(version) => {
+                criteria.incompatible.set(true);
+                continue;
+            },
+            _ => {},
+        }
+        // check if entry can take another lobby
+        if entry.info.has_capacity(game) {
+            candidates.push(entry);
         }
     }
-    None
+    // try the preferred regions in order before all other ones
+    for region in &criteria.regions {
+        let (matching, other) = candidates.into_iter()
+            .partition::<Vec<_>, _>(|x| match &x.info.region {
+                Some(x) => x.eq_ignore_ascii_case(region),
+                None => false,
+            });
+        candidates = other;
+        if let Some(result) = strategy.select(game, matching) {
+            return Some(result);
+        }
+    }
+    strategy.select(game, candidates)
 }
 
-async fn find_server_for_request(request: &NewRequest) -> Option<GameServer> {
-    let game = request.game.as_str();
+async fn find_server_for_request(criteria: &Criteria<'_>, request: &NewRequest) -> Option<GameServer> {
+    let strategy = super::selection::for_game(&request.game);
     let developer = request.developer.unwrap_or(false);
     let fallback = request.fallback.unwrap_or(true);
-    let empty = vec![];
-    let ignore = match &request.ignore {
-        Some(x) => x,
-        None => &empty,
-    };
     if developer {
-        if let Some(result) = find_server(game, true, false, ignore).await {
+        if let Some(result) = find_server(strategy, criteria, true, false).await {
             return Some(result);
         }
         if !fallback {
             return None;
         }
-        if let Some(result) = find_server(game, true, true, ignore).await {
+        if let Some(result) = find_server(strategy, criteria, true, true).await {
             return Some(result);
         }
     }
 
-    if let Some(result) = find_server(game, false, false, ignore).await {
+    if let Some(result) = find_server(strategy, criteria, false, false).await {
         return Some(result);
     }
     if !fallback {
         return None;
     }
-    find_server(game, false, true, ignore).await
+    find_server(strategy, criteria, false, true).await
+}
+
+fn parse_labels(labels: &Option<String>) -> Result<LabelSelector, HttpResponse> {
+    labels.as_deref()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| HttpResponse::BadRequest().json(json!({
+            "error": ApiError::new(400, e),
+        })))
 }
 
 async fn new(mut request: NewRequest) -> impl Responder {
@@ -234,9 +490,30 @@ async fn new(mut request: NewRequest) -> impl Responder {
             fallback: request.fallback,
             game: request.game,
             ignore: Some(ignore),
+            region: request.region,
+            labels: request.labels,
+            version: request.version,
         };
     }
-    match find_server_for_request(&request).await {
+    let labels = match parse_labels(&request.labels) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let version = match request.version.as_deref().map(Version::parse).transpose() {
+        Ok(x) => x,
+        Err(e) => return HttpResponse::BadRequest().json(json!({
+            "error": ApiError::new(400, format!("invalid version: {}", e)),
+        })),
+    };
+    let criteria = Criteria {
+        game: request.game.as_str(),
+        ignore: request.ignore.as_deref().unwrap_or_default(),
+        regions: request.regions(),
+        labels: &labels,
+        version,
+        incompatible: Cell::new(false),
+    };
+    match find_server_for_request(&criteria, &request).await {
         Some(result) => {
             let game_name = &request.game;
             HttpResponse::Ok().json(NewResponse {
@@ -254,7 +531,12 @@ async fn new(mut request: NewRequest) -> impl Responder {
                     .unwrap(),
             })
         },
-        None => HttpResponse::NotFound().finish(),
+        None => HttpResponse::NotFound().json(json!({
+            "error": ApiError::new(404, match criteria.incompatible.get() {
+                true => "no server for this version".to_string(),
+                false => "no server found".to_string(),
+            }),
+        })),
     }
 }
 
@@ -269,47 +551,144 @@ async fn new_post(request: web::Json<NewRequest>) -> impl Responder {
 }
 
 #[post("/v1/token")]
-async fn token_post(req: web::HttpRequest, request: web::Json<FastTokenAddRequest>) -> impl Responder {
-    let token = match get_header(&req, "token") {
-        Some(token) => token,
-        None => return HttpResponse::Forbidden().finish(),
+async fn token_post(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let token = match authenticate(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let request: FastTokenAddRequest = match parse_body(&body) {
+        Ok(x) => x,
+        Err(response) => return response,
     };
-    let server = match crate::db::model::Server::find_by_token(&token.as_str()) {
+    if let Err(err) = token.scope.check_fast_tokens()
+        .and_then(|()| token.scope.check_game(&request.game))
+    {
+        return error_response(err);
+    }
+    let conn = match crate::db::connection() {
+        Ok(x) => x,
+        Err(e) => return error_response(e),
+    };
+    let server = match crate::db::model::Server::find_by_token(&conn, &token.identity) {
         Ok(x) => x,
         Err(_) => return HttpResponse::Forbidden().finish(),
     };
-    let result: Result<crate::db::model::FastToken, _> = (server.id, request.into_inner()).try_into();
+    let result: Result<crate::db::model::FastToken, _> = (server.id, request).try_into();
     match result {
         Ok(res) => HttpResponse::Ok().json(Into::<FastTokenAddResponse>::into(res)),
-        Err(e) =>
-            HttpResponse::InternalServerError().json(json!({
-                "error": e,
-            })),
+        Err(e) => error_response(e),
     }
 }
 
-#[get("/v1/token/{token}")]
-async fn token_get(token: web::Path<String>) -> impl Responder {let now = chrono::Utc::now();
-    let limit = now.checked_sub_signed(
-        chrono::Duration::minutes(20)
-    )
-        .expect("limit traveled back in time")
-        .naive_utc();
-    let result = crate::db::model::FastToken::find_by_token_checked(
-        &token.into_inner().to_uppercase(), 
-        limit
-    );
+/// Lists the fast tokens of this server that can still be redeemed.
+#[get("/v1/token")]
+async fn token_list(req: web::HttpRequest, body: web::Bytes) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let now = chrono::Utc::now().naive_utc();
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::FastToken::find_active_by_server(&conn, server.id, now));
     match result {
-        Ok(x) => {
-            let x: Result<FastTokenFetchResponse, _> = x.try_into();
-            match x {
-                Ok(x) => HttpResponse::Ok().json(x),
-                Err(e) => HttpResponse::InternalServerError().json(json!({
-                    "error": e,
-                })),
+        Ok(x) => HttpResponse::Ok().json(x.into_iter()
+            .map(FastTokenEntry::from)
+            .collect::<Vec<_>>()
+        ),
+        Err(e) => error_response(e),
+    }
+}
+
+#[delete("/v1/token/{token}")]
+async fn token_delete(
+    req: web::HttpRequest,
+    body: web::Bytes,
+    token: web::Path<String>
+) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let token = super::fast_token::normalize(&token);
+    let result = crate::db::connection()
+        .and_then(|conn| crate::db::model::FastToken::delete_by_server_and_token(&conn, server.id, &token));
+    match result {
+        Ok(Some(x)) => HttpResponse::Ok().json(FastTokenEntry::from(x)),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// The maximum number of characters of the client info of a redemption.
+const MAX_CLIENT_INFO_LENGTH: usize = 256;
+
+#[get("/v1/token/{token}")]
+async fn token_get(token: web::Path<String>, query: web::Query<FastTokenFetchQuery>) -> impl Responder {
+    let now = chrono::Utc::now().naive_utc();
+    let client = query.into_inner().client
+        .map(|x| x.chars().take(MAX_CLIENT_INFO_LENGTH).collect::<String>());
+    let token = super::fast_token::normalize(&token);
+    // the token is first looked up as it is, because the alphabet of other
+    // games can contain the confusable characters
+    let mut candidates = vec![token];
+    if crate::config::crockford_lookup() {
+        candidates.extend(super::fast_token::normalize_crockford(&candidates[0]));
+    }
+    let result: Result<Option<FastTokenFetchResponse>, _> = crate::db::transaction(|conn| {
+        for candidate in &candidates {
+            if let Some(token) = crate::db::model::FastToken::redeem(conn, candidate, now)? {
+                crate::db::model::FastTokenRedemption::create(
+                    conn,
+                    crate::db::model::FastTokenRedemption {
+                        id: Uuid::new_v4(),
+                        fast_token_id: Some(token.id),
+                        token: token.token.clone(),
+                        server_id: token.server_id,
+                        client,
+                        created_at: now,
+                    }
+                )?;
+                return (conn, token).try_into().map(Some);
             }
-        },
-        Err(_) => HttpResponse::NotFound().finish(),
+        }
+        Ok(None)
+    });
+    match result {
+        Ok(Some(x)) => HttpResponse::Ok().json(x),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) if e.status_code == 404 => HttpResponse::NotFound().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Returns how often a fast token of this server was redeemed. The redemptions
+/// of deleted tokens are kept until `REDEMPTION_RETENTION`.
+#[get("/v1/token/{token}/redemptions")]
+async fn token_redemptions(
+    req: web::HttpRequest,
+    body: web::Bytes,
+    token: web::Path<String>
+) -> impl Responder {
+    let (server, _) = match authorized_server(&req, &body).await {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+    let token = super::fast_token::normalize(&token);
+    let result: Result<FastTokenRedemptionsResponse, _> = crate::db::snapshot(|conn| {
+        match crate::db::model::FastToken::find_by_token(conn, &token) {
+            Ok(x) if x.server_id == server.id => return (conn, x).try_into(),
+            Err(e) if e.status_code != 404 => return Err(e),
+            _ => {},
+        }
+        let redemptions = crate::db::model::FastTokenRedemption::find_deleted(conn, server.id, &token)?;
+        if redemptions.is_empty() {
+            return Err(ApiError::new(404, "Record not found".to_string()));
+        }
+        Ok(FastTokenRedemptionsResponse::deleted(token, redemptions))
+    });
+    match result {
+        Ok(x) => HttpResponse::Ok().json(x),
+        Err(e) => error_response(e),
     }
 }
 
@@ -319,10 +698,19 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(index_yml);
     cfg.service(index_json);
     cfg.service(update);
+    cfg.service(update_patch);
+    cfg.service(deregister);
+    cfg.service(game_put);
+    cfg.service(game_patch);
+    cfg.service(game_delete);
+    cfg.service(heartbeat);
     cfg.service(list);
     cfg.service(info);
     cfg.service(new_get);
     cfg.service(new_post);
     cfg.service(token_post);
+    cfg.service(token_list);
     cfg.service(token_get);
+    cfg.service(token_delete);
+    cfg.service(token_redemptions);
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use std::env;
+use super::model::{GameLoad, GameServer};
+
+/// Decides which of the candidate servers gets the next lobby of a game. All
+/// candidates are already checked to be online and to support the game.
+pub trait SelectionStrategy: Send + Sync {
+    fn select(&self, game: &str, candidates: Vec<GameServer>) -> Option<GameServer>;
+}
+
+/// The strategies an operator can choose from in the configuration.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    FirstFit,
+    LeastLoaded,
+    WeightedRandom,
+    RoundRobin,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    /// Uses the names of the serde derive, so that the environment and the
+    /// game config accept the same names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Strategy::deserialize(s.into_deserializer())
+            .map_err(|e: serde::de::value::Error| e.to_string())
+    }
+}
+
+impl Strategy {
+    fn create(self) -> Box<dyn SelectionStrategy> {
+        match self {
+            Strategy::FirstFit => Box::new(FirstFit),
+            Strategy::LeastLoaded => Box::new(LeastLoaded),
+            Strategy::WeightedRandom => Box::new(WeightedRandom),
+            Strategy::RoundRobin => Box::new(RoundRobin::default()),
+        }
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT: Box<dyn SelectionStrategy> = {
+        let strategy = match env::var("SELECTION_STRATEGY") {
+            Ok(x) => x.parse().expect("invalid selection strategy"),
+            Err(_) => Strategy::LeastLoaded,
+        };
+        info!("use {:?} as default selection strategy", strategy);
+        strategy.create()
+    };
+
+    static ref GAMES: HashMap<String, Box<dyn SelectionStrategy>> = {
+        crate::config::games()
+            .filter_map(|(game, config)| {
+                let strategy = config.strategy?;
+                info!("use {:?} as selection strategy for {}", strategy, game);
+                Some((game.clone(), strategy.create()))
+            })
+            .collect()
+    };
+}
+
+pub fn init() {
+    info!("Initializing Selection Strategies");
+    lazy_static::initialize(&DEFAULT);
+    lazy_static::initialize(&GAMES);
+}
+
+/// Returns the strategy that is configured for this game.
+pub fn for_game(game: &str) -> &'static dyn SelectionStrategy {
+    match GAMES.get(game) {
+        Some(strategy) => strategy.as_ref(),
+        None => DEFAULT.as_ref(),
+    }
+}
+
+fn sort_by_id(candidates: &mut [GameServer]) {
+    candidates.sort_by(|a, b| a.id.cmp(&b.id));
+}
+
+/// Selects always the first candidate in a stable order. New lobbies are packed
+/// into one server until it is no longer available.
+pub struct FirstFit;
+
+impl SelectionStrategy for FirstFit {
+    fn select(&self, _game: &str, mut candidates: Vec<GameServer>) -> Option<GameServer> {
+        sort_by_id(&mut candidates);
+        candidates.into_iter().next()
+    }
+}
+
+/// Selects the candidate with the lowest load. Ties are broken randomly so that a
+/// burst of requests is spread over all servers with the same load.
+pub struct LeastLoaded;
+
+impl SelectionStrategy for LeastLoaded {
+    fn select(&self, game: &str, mut candidates: Vec<GameServer>) -> Option<GameServer> {
+        let loads = candidates.iter()
+            .map(|x| x.load(game))
+            .collect::<Vec<_>>();
+        let min = loads.iter()
+            .flatten()
+            .fold(None, |min: Option<&GameLoad>, load| match min {
+                Some(min) if min <= load => Some(min),
+                _ => Some(load),
+            })?;
+        let pick = *loads.iter()
+            .enumerate()
+            .filter(|(_, load)| load.as_ref() == Some(min))
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>()
+            .choose(&mut rand::thread_rng())?;
+        Some(candidates.swap_remove(pick))
+    }
+}
+
+/// Selects a random candidate. The chance of each candidate is weighted by its
+/// remaining capacity.
+pub struct WeightedRandom;
+
+impl SelectionStrategy for WeightedRandom {
+    fn select(&self, game: &str, mut candidates: Vec<GameServer>) -> Option<GameServer> {
+        let weights = candidates.iter()
+            .map(|x| match x.load(game) {
+                // fully utilised servers still get a small chance
+                Some(load) => (1.0 - load.utilisation).max(0.01),
+                None => 0.0,
+            })
+            .collect::<Vec<_>>();
+        let dist = WeightedIndex::new(&weights).ok()?;
+        let pick = dist.sample(&mut rand::thread_rng());
+        Some(candidates.swap_remove(pick))
+    }
+}
+
+/// Selects the candidates in turn. Each game has its own turn.
+#[derive(Default)]
+pub struct RoundRobin {
+    next: Mutex<HashMap<String, usize>>,
+}
+
+impl SelectionStrategy for RoundRobin {
+    fn select(&self, game: &str, mut candidates: Vec<GameServer>) -> Option<GameServer> {
+        if candidates.is_empty() {
+            return None;
+        }
+        sort_by_id(&mut candidates);
+        let mut next = self.next.lock()
+            .expect("round robin state poisoned");
+        let turn = next.entry(game.to_string()).or_insert(0);
+        let pick = *turn % candidates.len();
+        *turn = pick + 1;
+        Some(candidates.swap_remove(pick))
+    }
+}